@@ -0,0 +1,565 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{error::AsmError, memory::PROGRAM_SPACE_START};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_CONSTANT_DEPTH: usize = 32;
+const ADDRESS_SPACE: u32 = 0x10000;
+const MNEMONICS: [&str; 30] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SYS", "SCD", "SCU", "JP", "CALL",
+    "SE", "SNE", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP",
+    "SKNP", "PLANE", "PITCH", "LD",
+];
+const RESERVED: [&str; 10] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU"];
+
+#[derive(Debug, Clone)]
+struct Location {
+    path: Option<Rc<Path>>,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError {
+            path: self.path.as_deref().map(Path::to_path_buf),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    column: usize,
+}
+
+#[derive(Debug)]
+struct Statement {
+    location: Location,
+    mnemonic: Token,
+    operands: Vec<Token>,
+}
+
+#[derive(Debug)]
+enum Symbol {
+    Label(u16),
+    // Evaluated on use, so constants may refer to labels defined later
+    Constant(Location, Token),
+}
+
+#[derive(Debug)]
+enum Operand {
+    Register(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Token),
+    Value(Token),
+}
+
+// Assembles Cowgod-style mnemonics into a ROM starting at 0x200. Includes are resolved
+// relative to `path`, or to the working directory if no path is given.
+pub fn assemble(source: &str, path: Option<&Path>) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        statements: Vec::new(),
+        address: PROGRAM_SPACE_START as u32,
+    };
+
+    assembler.parse(source, path.map(Rc::from), 0)?;
+    assembler.emit()
+}
+
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+    statements: Vec<Statement>,
+    address: u32,
+}
+
+impl Assembler {
+    // First pass, collects statements and assigns addresses to labels
+    fn parse(
+        &mut self,
+        source: &str,
+        path: Option<Rc<Path>>,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        for (index, line) in source.lines().enumerate() {
+            let location = Location {
+                path: path.clone(),
+                line: index + 1,
+            };
+            let code = strip_comment(line);
+
+            let mut word = next_word(code, 0);
+            if let Some((start, end)) = word {
+                if let Some(label) = code[start..end].strip_suffix(':') {
+                    // A label after the last byte of the address space can't be referenced
+                    let address = u16::try_from(self.address).map_err(|_| {
+                        location.error(
+                            start + 1,
+                            format!("Label address 0x{:X} is out of range", self.address),
+                        )
+                    })?;
+                    self.define(&location, label, start + 1, Symbol::Label(address))?;
+                    word = next_word(code, end);
+                }
+            }
+
+            let (start, end) = match word {
+                Some(word) => word,
+                None => continue,
+            };
+            let mnemonic = Token {
+                text: code[start..end].to_string(),
+                column: start + 1,
+            };
+
+            // NAME EQU expr
+            if let Some((equ_start, equ_end)) = next_word(code, end) {
+                if code[equ_start..equ_end].eq_ignore_ascii_case("EQU") {
+                    let value = token(code, equ_end, &code[equ_end..]);
+                    let constant = Symbol::Constant(location.clone(), value);
+                    self.define(&location, &mnemonic.text, mnemonic.column, constant)?;
+                    continue;
+                }
+            }
+
+            if mnemonic.text.eq_ignore_ascii_case("INCLUDE") {
+                self.include(&location, code, end, depth)?;
+                continue;
+            }
+
+            let operands = split_operands(code, end);
+            self.address += statement_size(&mnemonic.text, &operands);
+            if self.address > ADDRESS_SPACE {
+                return Err(location.error(mnemonic.column, "Program exceeds the address space"));
+            }
+
+            self.statements.push(Statement {
+                location,
+                mnemonic,
+                operands,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        location: &Location,
+        code: &str,
+        from: usize,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let argument = token(code, from, &code[from..]);
+        let file = argument
+            .text
+            .strip_prefix('"')
+            .and_then(|file| file.strip_suffix('"'))
+            .ok_or_else(|| location.error(argument.column, "Expected a quoted file name"))?;
+
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(location.error(argument.column, "Includes are nested too deeply"));
+        }
+
+        let resolved = match &location.path {
+            Some(path) => path.parent().unwrap_or_else(|| Path::new("")).join(file),
+            None => PathBuf::from(file),
+        };
+        let source = fs::read_to_string(&resolved).map_err(|err| {
+            location.error(
+                argument.column,
+                format!("Failed to read {}: {}", resolved.display(), err),
+            )
+        })?;
+
+        self.parse(&source, Some(Rc::from(resolved)), depth + 1)
+    }
+
+    fn define(
+        &mut self,
+        location: &Location,
+        name: &str,
+        column: usize,
+        symbol: Symbol,
+    ) -> Result<(), AsmError> {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            && parse_register(name).is_none()
+            && !RESERVED.contains(&name.to_ascii_uppercase().as_str());
+
+        if !valid {
+            return Err(location.error(column, format!("Invalid symbol name '{}'", name)));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(location.error(column, format!("Symbol '{}' is already defined", name)));
+        }
+
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    // Second pass, encodes statements now that every symbol is known
+    fn emit(&self) -> Result<Vec<u8>, AsmError> {
+        let mut output = Vec::new();
+
+        for statement in &self.statements {
+            self.encode(statement, &mut output)?;
+        }
+
+        Ok(output)
+    }
+
+    fn encode(&self, statement: &Statement, output: &mut Vec<u8>) -> Result<(), AsmError> {
+        use Operand::*;
+
+        let location = &statement.location;
+        let mnemonic = &statement.mnemonic;
+        let name = mnemonic.text.to_ascii_uppercase();
+
+        match name.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    output.push(self.signed_value(location, operand, 0xFF)? as u8);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    output.extend(self.signed_value(location, operand, 0xFFFF)?.to_be_bytes());
+                }
+                return Ok(());
+            }
+            _ => (),
+        }
+
+        let operands = statement
+            .operands
+            .iter()
+            .map(|operand| self.operand(location, operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        let invalid = || {
+            location.error(
+                mnemonic.column,
+                format!("Invalid operands for {}", mnemonic.text),
+            )
+        };
+        let value = |token: &Token, max| self.value(location, token, max);
+        let byte = |token: &Token| self.signed_value(location, token, 0xFF);
+        let xy = |opcode: u16, x: u8, y: u8| opcode | (x as u16) << 8 | (y as u16) << 4;
+
+        let opcode = match (name.as_str(), operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("AUDIO", []) => 0xF002,
+            ("SYS", [Value(addr)]) => value(addr, 0xFFF)?,
+            ("SCD", [Value(n)]) => 0x00C0 | value(n, 0xF)?,
+            ("SCU", [Value(n)]) => 0x00D0 | value(n, 0xF)?,
+            ("JP", [Value(addr)]) => 0x1000 | value(addr, 0xFFF)?,
+            ("JP", [Register(0), Value(addr)]) => 0xB000 | value(addr, 0xFFF)?,
+            ("CALL", [Value(addr)]) => 0x2000 | value(addr, 0xFFF)?,
+            ("SE", [Register(x), Value(kk)]) => xy(0x3000, *x, 0) | byte(kk)?,
+            ("SNE", [Register(x), Value(kk)]) => xy(0x4000, *x, 0) | byte(kk)?,
+            ("SE", [Register(x), Register(y)]) => xy(0x5000, *x, *y),
+            ("SNE", [Register(x), Register(y)]) => xy(0x9000, *x, *y),
+            ("ADD", [Register(x), Value(kk)]) => xy(0x7000, *x, 0) | byte(kk)?,
+            ("ADD", [Register(x), Register(y)]) => xy(0x8004, *x, *y),
+            ("ADD", [I, Register(x)]) => xy(0xF01E, *x, 0),
+            ("OR", [Register(x), Register(y)]) => xy(0x8001, *x, *y),
+            ("AND", [Register(x), Register(y)]) => xy(0x8002, *x, *y),
+            ("XOR", [Register(x), Register(y)]) => xy(0x8003, *x, *y),
+            ("SUB", [Register(x), Register(y)]) => xy(0x8005, *x, *y),
+            ("SHR", [Register(x), Register(y)]) => xy(0x8006, *x, *y),
+            ("SHR", [Register(x)]) => xy(0x8006, *x, *x),
+            ("SUBN", [Register(x), Register(y)]) => xy(0x8007, *x, *y),
+            ("SHL", [Register(x), Register(y)]) => xy(0x800E, *x, *y),
+            ("SHL", [Register(x)]) => xy(0x800E, *x, *x),
+            ("RND", [Register(x), Value(kk)]) => xy(0xC000, *x, 0) | byte(kk)?,
+            ("DRW", [Register(x), Register(y), Value(n)]) => xy(0xD000, *x, *y) | value(n, 0xF)?,
+            ("SKP", [Register(x)]) => xy(0xE09E, *x, 0),
+            ("SKNP", [Register(x)]) => xy(0xE0A1, *x, 0),
+            ("PLANE", [Value(n)]) => xy(0xF001, value(n, 0x3)? as u8, 0),
+            ("PITCH", [Register(x)]) => xy(0xF03A, *x, 0),
+            ("LD", [Register(x), Register(y)]) => xy(0x8000, *x, *y),
+            ("LD", [Register(x), Value(kk)]) => xy(0x6000, *x, 0) | byte(kk)?,
+            ("LD", [Register(x), Dt]) => xy(0xF007, *x, 0),
+            ("LD", [Register(x), K]) => xy(0xF00A, *x, 0),
+            ("LD", [Register(x), IndirectI]) => xy(0xF065, *x, 0),
+            ("LD", [Register(x), R]) => xy(0xF085, *x, 0),
+            ("LD", [Dt, Register(x)]) => xy(0xF015, *x, 0),
+            ("LD", [St, Register(x)]) => xy(0xF018, *x, 0),
+            ("LD", [F, Register(x)]) => xy(0xF029, *x, 0),
+            ("LD", [Hf, Register(x)]) => xy(0xF030, *x, 0),
+            ("LD", [B, Register(x)]) => xy(0xF033, *x, 0),
+            ("LD", [IndirectI, Register(x)]) => xy(0xF055, *x, 0),
+            ("LD", [R, Register(x)]) => xy(0xF075, *x, 0),
+            ("LD", [IndirectI, Range(x, y)]) => xy(0x5002, *x, *y),
+            ("LD", [Range(x, y), IndirectI]) => xy(0x5003, *x, *y),
+            ("LD", [I, Value(addr)]) => 0xA000 | value(addr, 0xFFF)?,
+            ("LD", [I, Long(addr)]) => {
+                output.extend(0xF000u16.to_be_bytes());
+                value(addr, 0xFFFF)?
+            }
+            _ if MNEMONICS.contains(&name.as_str()) => return Err(invalid()),
+            _ => {
+                return Err(location.error(
+                    mnemonic.column,
+                    format!("Unknown instruction '{}'", mnemonic.text),
+                ))
+            }
+        };
+
+        output.extend(opcode.to_be_bytes());
+        Ok(())
+    }
+
+    fn operand(&self, location: &Location, token: &Token) -> Result<Operand, AsmError> {
+        let text = token.text.to_ascii_uppercase();
+
+        Ok(match text.as_str() {
+            "" => return Err(location.error(token.column, "Expected an operand")),
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ => {
+                if let Some(x) = parse_register(&text) {
+                    Operand::Register(x)
+                } else if let Some((x, y)) = text
+                    .split_once('-')
+                    .and_then(|(x, y)| Some((parse_register(x.trim())?, parse_register(y.trim())?)))
+                {
+                    Operand::Range(x, y)
+                } else if text.starts_with("LONG ") {
+                    Operand::Long(token_at(token, "LONG".len()))
+                } else {
+                    Operand::Value(token.clone())
+                }
+            }
+        })
+    }
+
+    // Evaluates an address, nibble or other unsigned field between 0 and `max`
+    fn value(&self, location: &Location, token: &Token, max: u16) -> Result<u16, AsmError> {
+        self.ranged_value(location, token, 0, max)
+    }
+
+    // Evaluates a byte or data word, negative values are stored as two's complement
+    fn signed_value(&self, location: &Location, token: &Token, max: u16) -> Result<u16, AsmError> {
+        self.ranged_value(location, token, -((max as i64 + 1) / 2), max)
+    }
+
+    fn ranged_value(
+        &self,
+        location: &Location,
+        token: &Token,
+        min: i64,
+        max: u16,
+    ) -> Result<u16, AsmError> {
+        let value = self.evaluate(location, token, 0)?;
+
+        if value < min || value > max as i64 {
+            return Err(location.error(
+                token.column,
+                format!("Value {} is out of range ({} to 0x{:X})", value, min, max),
+            ));
+        }
+
+        Ok((value & max as i64) as u16)
+    }
+
+    // Expressions are sums of numbers and symbols: `label + 2`, `SIZE - 1`
+    fn evaluate(&self, location: &Location, token: &Token, depth: usize) -> Result<i64, AsmError> {
+        let text = &token.text;
+        let mut total = 0i64;
+        let mut sign = 1i64;
+        let mut term_start = None;
+
+        for (index, c) in text.char_indices().chain(Some((text.len(), '+'))) {
+            match c {
+                '+' | '-' => {
+                    match term_start.take() {
+                        Some(start) => {
+                            let term = Token {
+                                text: text[start..index].trim().to_string(),
+                                column: token.column + start,
+                            };
+                            let value = self.term(location, &term, depth)?;
+                            total = match sign {
+                                1 => total.checked_add(value),
+                                _ => total.checked_sub(value),
+                            }
+                            .ok_or_else(|| location.error(token.column, "Expression overflows"))?;
+                            sign = 1;
+                        }
+                        None if index == text.len() => {
+                            return Err(location.error(token.column, "Expected a value"))
+                        }
+                        None => (),
+                    }
+                    if c == '-' {
+                        sign = -sign;
+                    }
+                }
+                c if c.is_whitespace() => (),
+                _ => {
+                    term_start.get_or_insert(index);
+                }
+            }
+        }
+
+        Ok(total)
+    }
+
+    fn term(&self, location: &Location, token: &Token, depth: usize) -> Result<i64, AsmError> {
+        let text = token.text.as_str();
+        let invalid = || location.error(token.column, format!("Invalid number '{}'", text));
+
+        if text.starts_with(|c: char| c.is_ascii_digit() || c == '#' || c == '$' || c == '%') {
+            let (digits, radix) = if let Some(hex) = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .or_else(|| text.strip_prefix('#'))
+                .or_else(|| text.strip_prefix('$'))
+            {
+                (hex, 16)
+            } else if let Some(bin) = text
+                .strip_prefix("0b")
+                .or_else(|| text.strip_prefix("0B"))
+                .or_else(|| text.strip_prefix('%'))
+            {
+                (bin, 2)
+            } else {
+                (text, 10)
+            };
+
+            return i64::from_str_radix(digits, radix).map_err(|_| invalid());
+        }
+
+        match self.symbols.get(text) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(_, _)) if depth >= MAX_CONSTANT_DEPTH => Err(location.error(
+                token.column,
+                format!("Constant '{}' refers to itself", text),
+            )),
+            Some(Symbol::Constant(location, value)) => self.evaluate(location, value, depth + 1),
+            None => Err(location.error(token.column, format!("Undefined symbol '{}'", text))),
+        }
+    }
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V' | 'v'), Some(x), None) => x.to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn statement_size(mnemonic: &str, operands: &[Token]) -> u32 {
+    if mnemonic.eq_ignore_ascii_case("DB") {
+        operands.len() as u32
+    } else if mnemonic.eq_ignore_ascii_case("DW") {
+        operands.len() as u32 * 2
+    } else if mnemonic.eq_ignore_ascii_case("LD")
+        && operands
+            .get(1)
+            .is_some_and(|operand| operand.text.to_ascii_uppercase().starts_with("LONG "))
+    {
+        4
+    } else {
+        2
+    }
+}
+
+// Everything after ';' is a comment, unless it's inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => (),
+        }
+    }
+
+    line
+}
+
+// Byte range of the next whitespace separated word at or after `from`
+fn next_word(code: &str, from: usize) -> Option<(usize, usize)> {
+    let start = from + code[from..].find(|c: char| !c.is_whitespace())?;
+    let end = code[start..]
+        .find(char::is_whitespace)
+        .map_or(code.len(), |len| start + len);
+
+    Some((start, end))
+}
+
+// Trimmed `part` of `code`, which starts at byte `offset`
+fn token(code: &str, offset: usize, part: &str) -> Token {
+    let leading = part.len() - part.trim_start().len();
+
+    Token {
+        text: part.trim().to_string(),
+        column: offset.min(code.len()) + leading + 1,
+    }
+}
+
+// Remainder of `token` after skipping `skip` bytes
+fn token_at(token: &Token, skip: usize) -> Token {
+    let rest = &token.text[skip..];
+    let leading = rest.len() - rest.trim_start().len();
+
+    Token {
+        text: rest.trim().to_string(),
+        column: token.column + skip + leading,
+    }
+}
+
+fn split_operands(code: &str, from: usize) -> Vec<Token> {
+    if code[from..].trim().is_empty() {
+        return Vec::new();
+    }
+
+    let mut operands = Vec::new();
+    let mut offset = from;
+    for part in code[from..].split(',') {
+        operands.push(token(code, offset, part));
+        offset += part.len() + 1;
+    }
+
+    operands
+}
@@ -0,0 +1,161 @@
+use std::f32::consts::TAU;
+
+use crate::emulator::{ChipEmulator, ChipVariant};
+
+// Bits in the XO-CHIP audio pattern buffer
+const PATTERN_BITS: f64 = 128.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeeperConfig {
+    pub waveform: Waveform,
+    // Tone frequency in Hz
+    pub frequency: f32,
+    // Amplitude between 0.0 and 1.0
+    pub volume: f32,
+}
+
+impl Default for BeeperConfig {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+// Audio backend receiving mono samples between -1.0 and 1.0
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    fn write(&mut self, samples: &[f32]);
+}
+
+// Playback rate of the XO-CHIP pattern buffer in bits per second, 4000 Hz at the default pitch of 64
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+// Renders the 1-bit XO-CHIP pattern buffer, looping over its 128 bits
+#[derive(Debug, Clone, Default)]
+pub struct PatternSynth {
+    // Bit position within the pattern, between 0.0 and 128.0
+    position: f64,
+}
+
+impl PatternSynth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Starts the next note at the beginning of the pattern
+    pub fn restart(&mut self) {
+        self.position = 0.0;
+    }
+
+    pub fn render(
+        &mut self,
+        pattern: &[u8; 16],
+        pitch: u8,
+        sample_rate: u32,
+        volume: f32,
+        out: &mut [f32],
+    ) {
+        let step = pattern_rate(pitch) / sample_rate as f64;
+
+        for sample in out {
+            let bit = self.position as usize;
+            let set = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if set { volume } else { -volume };
+            self.position = (self.position + step) % PATTERN_BITS;
+        }
+    }
+}
+
+// Plays a tone while the sound timer is non-zero. XO-CHIP programs that ran F002 play
+// the loaded audio pattern, even a silent one, at the pitch register's rate instead
+#[derive(Debug, Clone)]
+pub struct Beeper {
+    config: BeeperConfig,
+    // Position within the current wave period, between 0.0 and 1.0
+    phase: f32,
+    pattern: PatternSynth,
+    // Sound timer was running during the previous frame
+    playing: bool,
+    // Fractional samples carried over to the next frame
+    remainder: f64,
+    buffer: Vec<f32>,
+}
+
+impl Beeper {
+    pub fn new(config: BeeperConfig) -> Self {
+        Self {
+            config,
+            phase: 0.0,
+            pattern: PatternSynth::new(),
+            playing: false,
+            remainder: 0.0,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BeeperConfig {
+        &self.config
+    }
+
+    // Generates one 60 Hz frame worth of samples for the current sound timer
+    pub fn render(&mut self, emulator: &ChipEmulator, sample_rate: u32) -> &[f32] {
+        let samples = sample_rate as f64 / 60.0 + self.remainder;
+        self.remainder = samples.fract();
+
+        self.buffer.clear();
+        self.buffer.resize(samples as usize, 0.0);
+
+        let playing = emulator.timers().sound > 0;
+        if !playing {
+            self.playing = false;
+            return &self.buffer;
+        }
+        if !self.playing {
+            self.pattern.restart();
+            self.playing = true;
+        }
+
+        let pattern = emulator.audio_pattern();
+        if emulator.config().variant == ChipVariant::XoChip && emulator.audio_loaded() {
+            self.pattern.render(
+                pattern,
+                emulator.pitch(),
+                sample_rate,
+                self.config.volume,
+                &mut self.buffer,
+            );
+            return &self.buffer;
+        }
+
+        let step = self.config.frequency / sample_rate as f32;
+        for sample in self.buffer.iter_mut() {
+            let value = match self.config.waveform {
+                Waveform::Square if self.phase < 0.5 => 1.0,
+                Waveform::Square => -1.0,
+                Waveform::Sine => (self.phase * TAU).sin(),
+            };
+            *sample = value * self.config.volume;
+            self.phase = (self.phase + step).fract();
+        }
+
+        &self.buffer
+    }
+
+    pub fn frame(&mut self, emulator: &ChipEmulator, sink: &mut dyn AudioSink) {
+        let sample_rate = sink.sample_rate();
+        sink.write(self.render(emulator, sample_rate));
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+
+use crate::{
+    emulator::{ChipEmulator, StepOutcome},
+    error::ChipError,
+    instruction::Instruction,
+    memory::{AccessKind, MemoryAccess},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugRegister {
+    V(u8),
+    I,
+    Delay,
+    Sound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: DebugRegister,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    // Only break when the condition holds
+    pub condition: Option<Condition>,
+}
+
+// Inclusive address range watched for the enabled access kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub watchpoint: Watchpoint,
+    pub access: MemoryAccess,
+    // Address and instruction that made the access
+    pub pc: u16,
+    pub instruction: Instruction,
+}
+
+// Why a debugger run returned control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Stepped,
+    Reached,
+    Breakpoint(u16),
+    Watchpoint(WatchpointHit),
+    WaitingForKey,
+    Exited,
+    CycleLimit,
+}
+
+impl DebugRegister {
+    pub fn value(&self, emulator: &ChipEmulator) -> u16 {
+        match *self {
+            Self::V(x) => emulator.registers()[x] as u16,
+            Self::I => emulator.i(),
+            Self::Delay => emulator.timers().delay as u16,
+            Self::Sound => emulator.timers().sound as u16,
+        }
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, emulator: &ChipEmulator) -> bool {
+        let value = self.register.value(emulator);
+
+        match self.comparison {
+            Comparison::Eq => value == self.value,
+            Comparison::Ne => value != self.value,
+            Comparison::Lt => value < self.value,
+            Comparison::Le => value <= self.value,
+            Comparison::Gt => value > self.value,
+            Comparison::Ge => value >= self.value,
+        }
+    }
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind = match access.kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        };
+
+        kind && access.overlaps(self.start as usize, self.end as usize)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:03X}-0x{:03X} (", self.start, self.end)?;
+        for (enabled, flag) in [(self.read, 'r'), (self.write, 'w'), (self.execute, 'x')] {
+            if enabled {
+                write!(f, "{}", flag)?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for DebugRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V(x) => write!(f, "V{:X}", x),
+            Self::I => f.write_str("I"),
+            Self::Delay => f.write_str("DT"),
+            Self::Sound => f.write_str("ST"),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} 0x{:X}",
+            self.register, self.comparison, self.value
+        )
+    }
+}
+
+impl ChipEmulator {
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    // Replaces any breakpoint already set at the same address
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.remove_breakpoint(breakpoint.address);
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|bp| bp.address != address);
+        self.breakpoints.len() != count
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    // Breakpoint at the current PC whose condition holds
+    pub fn hit_breakpoint(&self) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|bp| {
+            bp.address == self.pc() && bp.condition.is_none_or(|condition| condition.matches(self))
+        })
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Memory accesses are only recorded while watchpoints are set
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+        self.memory.set_access_tracking(true);
+    }
+
+    // Removes every watchpoint starting at `start`
+    pub fn remove_watchpoint(&mut self, start: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp.start != start);
+        if self.watchpoints.is_empty() {
+            self.memory.set_access_tracking(false);
+        }
+        self.watchpoints.len() != count
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+        self.memory.set_access_tracking(false);
+    }
+
+    // First watched access made by the last executed instruction
+    fn hit_watchpoint(&self, pc: u16, instruction: Instruction) -> Option<WatchpointHit> {
+        self.memory.accesses().iter().find_map(|access| {
+            let watchpoint = self.watchpoints.iter().find(|wp| wp.matches(access))?;
+            Some(WatchpointHit {
+                watchpoint: *watchpoint,
+                access: *access,
+                pc,
+                instruction,
+            })
+        })
+    }
+
+    // Executes a single instruction, breakpoints and watchpoints are ignored
+    pub fn step(&mut self) -> Result<StepOutcome, ChipError> {
+        self.tick()
+    }
+
+    // Like step, but runs a whole subroutine if the next instruction is a CALL
+    pub fn step_over(&mut self, max_cycles: u64) -> Result<StopReason, ChipError> {
+        match self.current_instruction() {
+            Some(Ok(Instruction::Call { .. })) => {
+                // A call in the last word of memory has nowhere to return to
+                let pc = self.pc();
+                let return_pc = pc.checked_add(2).ok_or(ChipError::PcOutOfRange { pc })?;
+                let sp = self.sp();
+                self.run_until(max_cycles, |emu| emu.pc() == return_pc && emu.sp() == sp)
+            }
+            _ => Ok(match self.step()? {
+                StepOutcome::WaitingForKey => StopReason::WaitingForKey,
+                StepOutcome::Exited => StopReason::Exited,
+                _ => StopReason::Stepped,
+            }),
+        }
+    }
+
+    // Runs until PC reaches `address` or a breakpoint is hit
+    pub fn run_to(&mut self, address: u16, max_cycles: u64) -> Result<StopReason, ChipError> {
+        self.run_until(max_cycles, |emu| emu.pc() == address)
+    }
+
+    // Runs until a breakpoint is hit
+    pub fn resume(&mut self, max_cycles: u64) -> Result<StopReason, ChipError> {
+        self.run_until(max_cycles, |_| false)
+    }
+
+    // The breakpoint at the starting PC is skipped, so runs can continue from a breakpoint.
+    // Watchpoints stop the run after the accessing instruction has executed
+    fn run_until(
+        &mut self,
+        max_cycles: u64,
+        mut done: impl FnMut(&Self) -> bool,
+    ) -> Result<StopReason, ChipError> {
+        for _ in 0..max_cycles {
+            let pc = self.pc();
+            let instruction = if self.watchpoints.is_empty() {
+                None
+            } else {
+                self.current_instruction().and_then(Result::ok)
+            };
+
+            match self.tick()? {
+                StepOutcome::WaitingForKey => return Ok(StopReason::WaitingForKey),
+                StepOutcome::Exited => return Ok(StopReason::Exited),
+                _ => (),
+            }
+
+            if let Some(hit) = instruction.and_then(|inst| self.hit_watchpoint(pc, inst)) {
+                return Ok(StopReason::Watchpoint(hit));
+            }
+            if done(self) {
+                return Ok(StopReason::Reached);
+            }
+            if self.hit_breakpoint().is_some() {
+                return Ok(StopReason::Breakpoint(self.pc()));
+            }
+        }
+
+        Ok(StopReason::CycleLimit)
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    emulator::ChipConfig,
+    instruction::{decode, Instruction},
+    memory::ChipOpcode,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    // Mnemonics from Cowgod's Chip-8 Technical Reference
+    #[default]
+    Cowgod,
+    // Statements accepted by the Octo assembler
+    Octo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmLine<'a> {
+    pub address: u16,
+    pub bytes: &'a [u8],
+    // None for bytes that are not reached as code
+    pub instruction: Option<Instruction>,
+}
+
+impl DisasmLine<'_> {
+    pub fn mnemonic(&self, syntax: Syntax) -> String {
+        match self.instruction {
+            // The address of F000 nnnn lives in the second word
+            Some(Instruction::LdILong) => format!(
+                "{} 0x{:04X}",
+                mnemonic(&Instruction::LdILong, syntax),
+                u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+            ),
+            Some(instruction) => mnemonic(&instruction, syntax),
+            None => match syntax {
+                Syntax::Cowgod => format!("DB 0x{:02X}", self.bytes[0]),
+                Syntax::Octo => format!("0x{:02X}", self.bytes[0]),
+            },
+        }
+    }
+}
+
+// Disassemble only the bytes reachable from `origin`, everything else is emitted as data
+pub fn disassemble<'a>(rom: &'a [u8], origin: u16, config: &ChipConfig) -> Vec<DisasmLine<'a>> {
+    let starts = trace_code(rom, origin, config);
+    build_lines(rom, origin, config, &starts)
+}
+
+// Decode every word in order, only falling back to data for invalid opcodes
+pub fn disassemble_linear<'a>(
+    rom: &'a [u8],
+    origin: u16,
+    config: &ChipConfig,
+) -> Vec<DisasmLine<'a>> {
+    let mut starts = vec![false; rom.len()];
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        match decode_at(rom, offset, config) {
+            Some(instruction) => {
+                starts[offset] = true;
+                offset += instruction.size() as usize;
+            }
+            None => offset += 1,
+        }
+    }
+
+    build_lines(rom, origin, config, &starts)
+}
+
+pub fn mnemonic(instruction: &Instruction, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => cowgod(instruction),
+        Syntax::Octo => octo(instruction),
+    }
+}
+
+fn decode_at(rom: &[u8], offset: usize, config: &ChipConfig) -> Option<Instruction> {
+    let word = rom.get(offset..offset + 2)?;
+    let instruction = decode(
+        ChipOpcode::from(u16::from_be_bytes([word[0], word[1]])),
+        config,
+    )
+    .ok()?;
+
+    (offset + instruction.size() as usize <= rom.len()).then_some(instruction)
+}
+
+// Follow jumps, calls and skips from `origin`, marking every offset an instruction starts at
+fn trace_code(rom: &[u8], origin: u16, config: &ChipConfig) -> Vec<bool> {
+    let mut starts = vec![false; rom.len()];
+    let mut pending = vec![origin];
+
+    while let Some(address) = pending.pop() {
+        let offset = match (address as usize).checked_sub(origin as usize) {
+            Some(offset) if offset < rom.len() && !starts[offset] => offset,
+            _ => continue,
+        };
+        let instruction = match decode_at(rom, offset, config) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        starts[offset] = true;
+        let next = address.wrapping_add(instruction.size());
+
+        match instruction {
+            Instruction::Jp { nnn } => pending.push(nnn),
+            Instruction::Call { nnn } => pending.extend([nnn, next]),
+            // Computed jumps can't be followed statically
+            Instruction::Ret | Instruction::Exit | Instruction::JpV0 { .. } => (),
+            skip if skip.is_skip() => {
+                let skipped = decode_at(rom, offset + instruction.size() as usize, config)
+                    .map_or(2, |instruction| instruction.size());
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    starts
+}
+
+fn build_lines<'a>(
+    rom: &'a [u8],
+    origin: u16,
+    config: &ChipConfig,
+    starts: &[bool],
+) -> Vec<DisasmLine<'a>> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let instruction = if starts[offset] {
+            decode_at(rom, offset, config)
+        } else {
+            None
+        };
+        let len = instruction.map_or(1, |instruction| instruction.size() as usize);
+
+        lines.push(DisasmLine {
+            address,
+            bytes: &rom[offset..offset + len],
+            instruction,
+        });
+        offset += len;
+    }
+
+    lines
+}
+
+fn cowgod(instruction: &Instruction) -> String {
+    use Instruction::*;
+
+    match *instruction {
+        Sys { nnn } => format!("SYS 0x{:03X}", nnn),
+        Cls => "CLS".into(),
+        Ret => "RET".into(),
+        ScrollDown { n } => format!("SCD {}", n),
+        ScrollUp { n } => format!("SCU {}", n),
+        ScrollRight => "SCR".into(),
+        ScrollLeft => "SCL".into(),
+        Exit => "EXIT".into(),
+        Low => "LOW".into(),
+        High => "HIGH".into(),
+        Jp { nnn } => format!("JP 0x{:03X}", nnn),
+        Call { nnn } => format!("CALL 0x{:03X}", nnn),
+        SeByte { x, kk } => format!("SE V{:X}, 0x{:02X}", x, kk),
+        SneByte { x, kk } => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        SeReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        SaveRange { x, y } => format!("LD [I], V{:X}-V{:X}", x, y),
+        LoadRange { x, y } => format!("LD V{:X}-V{:X}, [I]", x, y),
+        LdByte { x, kk } => format!("LD V{:X}, 0x{:02X}", x, kk),
+        AddByte { x, kk } => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        LdReg { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        AddReg { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Subn { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        SneReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        LdI { nnn } => format!("LD I, 0x{:03X}", nnn),
+        JpV0 { nnn } => format!("JP V0, 0x{:03X}", nnn),
+        Rnd { x, kk } => format!("RND V{:X}, 0x{:02X}", x, kk),
+        Drw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Skp { x } => format!("SKP V{:X}", x),
+        Sknp { x } => format!("SKNP V{:X}", x),
+        LdILong => "LD I, LONG".into(),
+        Plane { n } => format!("PLANE {}", n),
+        Audio => "AUDIO".into(),
+        LdVxDt { x } => format!("LD V{:X}, DT", x),
+        LdVxK { x } => format!("LD V{:X}, K", x),
+        LdDtVx { x } => format!("LD DT, V{:X}", x),
+        LdStVx { x } => format!("LD ST, V{:X}", x),
+        AddI { x } => format!("ADD I, V{:X}", x),
+        LdF { x } => format!("LD F, V{:X}", x),
+        LdHf { x } => format!("LD HF, V{:X}", x),
+        LdB { x } => format!("LD B, V{:X}", x),
+        Pitch { x } => format!("PITCH V{:X}", x),
+        Store { x } => format!("LD [I], V{:X}", x),
+        Load { x } => format!("LD V{:X}, [I]", x),
+        StoreFlags { x } => format!("LD R, V{:X}", x),
+        LoadFlags { x } => format!("LD V{:X}, R", x),
+    }
+}
+
+// Octo expresses skips as inverted `if ... then` conditions
+fn octo(instruction: &Instruction) -> String {
+    use Instruction::*;
+
+    match *instruction {
+        Sys { nnn } => format!("native 0x{:03X}", nnn),
+        Cls => "clear".into(),
+        Ret => "return".into(),
+        ScrollDown { n } => format!("scroll-down {}", n),
+        ScrollUp { n } => format!("scroll-up {}", n),
+        ScrollRight => "scroll-right".into(),
+        ScrollLeft => "scroll-left".into(),
+        Exit => "exit".into(),
+        Low => "lores".into(),
+        High => "hires".into(),
+        Jp { nnn } => format!("jump 0x{:03X}", nnn),
+        Call { nnn } => format!(":call 0x{:03X}", nnn),
+        SeByte { x, kk } => format!("if v{:x} != 0x{:02X} then", x, kk),
+        SneByte { x, kk } => format!("if v{:x} == 0x{:02X} then", x, kk),
+        SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+        SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+        LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+        LdByte { x, kk } => format!("v{:x} := 0x{:02X}", x, kk),
+        AddByte { x, kk } => format!("v{:x} += 0x{:02X}", x, kk),
+        LdReg { x, y } => format!("v{:x} := v{:x}", x, y),
+        Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+        And { x, y } => format!("v{:x} &= v{:x}", x, y),
+        Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+        AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+        Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+        Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+        Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+        Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+        SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+        LdI { nnn } => format!("i := 0x{:03X}", nnn),
+        JpV0 { nnn } => format!("jump0 0x{:03X}", nnn),
+        Rnd { x, kk } => format!("v{:x} := random 0x{:02X}", x, kk),
+        Drw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+        Skp { x } => format!("if v{:x} -key then", x),
+        Sknp { x } => format!("if v{:x} key then", x),
+        LdILong => "i := long".into(),
+        Plane { n } => format!("plane {}", n),
+        Audio => "audio".into(),
+        LdVxDt { x } => format!("v{:x} := delay", x),
+        LdVxK { x } => format!("v{:x} := key", x),
+        LdDtVx { x } => format!("delay := v{:x}", x),
+        LdStVx { x } => format!("buzzer := v{:x}", x),
+        AddI { x } => format!("i += v{:x}", x),
+        LdF { x } => format!("i := hex v{:x}", x),
+        LdHf { x } => format!("i := bighex v{:x}", x),
+        LdB { x } => format!("bcd v{:x}", x),
+        Pitch { x } => format!("pitch := v{:x}", x),
+        Store { x } => format!("save v{:x}", x),
+        Load { x } => format!("load v{:x}", x),
+        StoreFlags { x } => format!("saveflags v{:x}", x),
+        LoadFlags { x } => format!("loadflags v{:x}", x),
+    }
+}
@@ -1,47 +1,225 @@
-use bitvec::{array::BitArray, order::Msb0, view::BitView, BitArr};
-
-pub type DisplayIter<'a> = bitvec::slice::Iter<'a, u8, Msb0>;
+use bitvec::{array::BitArray, order::Msb0, slice::BitSlice, view::BitView, BitArr};
 
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+pub const DISPLAY_PLANES: usize = 2;
+pub const PLANE_BYTES: usize = HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT / 8;
+
+type PlaneBuffer = BitArr!(for HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT, in u8, Msb0);
+
+// Yields the bit-plane mask of every pixel (bit 0 = plane 1, bit 1 = plane 2)
+#[derive(Debug, Clone)]
+pub struct DisplayIter<'a> {
+    planes: [bitvec::slice::Iter<'a, u8, Msb0>; DISPLAY_PLANES],
+}
+
+impl Iterator for DisplayIter<'_> {
+    type Item = u8;
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct ChipDisplay(pub BitArr!(for DISPLAY_WIDTH * DISPLAY_HEIGHT, in u8, Msb0));
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = *self.planes[0].next()?;
+        let second = *self.planes[1].next()?;
+
+        Some(first as u8 | (second as u8) << 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.planes[0].size_hint()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChipDisplay {
+    planes: [PlaneBuffer; DISPLAY_PLANES],
+    selected: u8,
+    hires: bool,
+}
 
 impl ChipDisplay {
     pub fn new() -> Self {
-        Self(BitArray::ZERO)
+        Self {
+            planes: [BitArray::ZERO; DISPLAY_PLANES],
+            selected: 0b01,
+            hires: false,
+        }
     }
 
+    // Clears only the selected planes
     pub fn clear(&mut self) {
-        self.0 = BitArray::ZERO;
+        for plane in self.selected_planes() {
+            self.planes[plane] = BitArray::ZERO;
+        }
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
     }
 
-    pub fn draw_sprite(&mut self, (x, y): (u8, u8), sprite: &[u8]) -> bool {
-        let (x, y) = (x as usize, y as usize);
-        let coord_to_index = |(x, y): (usize, usize)| -> usize {
-            (y % DISPLAY_HEIGHT) * DISPLAY_WIDTH + (x % DISPLAY_WIDTH)
+    // Switching resolution also clears the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [BitArray::ZERO; DISPLAY_PLANES];
+    }
+
+    pub fn selected_planes_mask(&self) -> u8 {
+        self.selected
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & 0b11;
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selected.count_ones() as usize
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_DISPLAY_WIDTH
+        } else {
+            DISPLAY_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_DISPLAY_HEIGHT
+        } else {
+            DISPLAY_HEIGHT
+        }
+    }
+
+    // Sprite data holds one n-byte sprite for each selected plane, back to back
+    // clip = true: Pixels past the screen edges are dropped instead of wrapping around
+    pub fn draw_sprite(&mut self, coords: (u8, u8), sprite: &[u8], clip: bool) -> bool {
+        self.draw(coords, sprite, 1, clip)
+    }
+
+    // 16x16 sprite, 2 bytes per row
+    pub fn draw_large_sprite(&mut self, coords: (u8, u8), sprite: &[u8], clip: bool) -> bool {
+        self.draw(coords, sprite, 2, clip)
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+
+        self.for_each_selected(|plane| {
+            plane.copy_within(..(height - n) * width, n * width);
+            plane[..n * width].fill(false);
+        });
+    }
+
+    pub fn scroll_up(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+
+        self.for_each_selected(|plane| {
+            plane.copy_within(n * width..height * width, 0);
+            plane[(height - n) * width..height * width].fill(false);
+        });
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+
+        self.for_each_selected(|plane| {
+            for row in plane[..width * height].chunks_exact_mut(width) {
+                row.copy_within(..width - n, n);
+                row[..n].fill(false);
+            }
+        });
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+
+        self.for_each_selected(|plane| {
+            for row in plane[..width * height].chunks_exact_mut(width) {
+                row.copy_within(n.., 0);
+                row[width - n..].fill(false);
+            }
+        });
+    }
+
+    // Raw plane contents, row-major with the stride of the current resolution
+    pub fn plane_data(&self, plane: usize) -> &[u8] {
+        self.planes[plane].as_raw_slice()
+    }
+
+    pub fn plane_data_mut(&mut self, plane: usize) -> &mut [u8] {
+        self.planes[plane].as_raw_mut_slice()
+    }
+
+    pub fn iter(&self) -> DisplayIter<'_> {
+        let len = self.width() * self.height();
+
+        DisplayIter {
+            planes: [self.planes[0][..len].iter(), self.planes[1][..len].iter()],
+        }
+    }
+
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected;
+        (0..DISPLAY_PLANES).filter(move |plane| selected & (1 << plane) != 0)
+    }
+
+    fn for_each_selected(&mut self, mut f: impl FnMut(&mut BitSlice<u8, Msb0>)) {
+        for plane in self.selected_planes() {
+            f(self.planes[plane].as_mut_bitslice());
+        }
+    }
+
+    fn draw(&mut self, (x, y): (u8, u8), sprite: &[u8], bytes_per_row: usize, clip: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        // Starting position always wraps around
+        let (x, y) = (x as usize % width, y as usize % height);
+        let coord_to_index = |(x, y): (usize, usize)| -> Option<usize> {
+            if clip && (x >= width || y >= height) {
+                None
+            } else {
+                Some((y % height) * width + (x % width))
+            }
         };
 
+        let count = self.selected_count();
+        if count == 0 || sprite.is_empty() {
+            return false;
+        }
+
         let mut collision = false;
 
-        for (y_off, row) in sprite.iter().enumerate() {
-            for (x_off, pixel) in row.view_bits::<Msb0>().iter().enumerate() {
-                let index = coord_to_index((x + x_off, y + y_off));
-                let mut curr = self.0.get_mut(index).unwrap();
+        for (plane, data) in self
+            .selected_planes()
+            .zip(sprite.chunks(sprite.len() / count))
+        {
+            for (y_off, row) in data.chunks(bytes_per_row).enumerate() {
+                for (x_off, pixel) in row.view_bits::<Msb0>().iter().enumerate() {
+                    let index = match coord_to_index((x + x_off, y + y_off)) {
+                        Some(index) => index,
+                        None => continue,
+                    };
+                    let mut curr = self.planes[plane].get_mut(index).unwrap();
 
-                *curr ^= *pixel;
+                    *curr ^= *pixel;
 
-                if !curr && *pixel {
-                    collision = true;
+                    if !curr && *pixel {
+                        collision = true;
+                    }
                 }
             }
         }
 
         collision
     }
+}
 
-    pub fn iter(&self) -> DisplayIter {
-        self.0.iter()
+impl Default for ChipDisplay {
+    fn default() -> Self {
+        Self::new()
     }
 }
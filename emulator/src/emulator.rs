@@ -1,33 +1,95 @@
-use std::io::{self, Read};
-
-use fastrand::Rng;
+use std::io::{self, Read, Write};
 
 use crate::{
-    display::{ChipDisplay, DisplayIter},
-    keypad::ChipKey,
-    memory::{ChipMemory, PROGRAM_SPACE_START},
+    debugger::{Breakpoint, Watchpoint},
+    display::{ChipDisplay, DisplayIter, DISPLAY_PLANES, PLANE_BYTES},
+    error::{ChipError, DecodeError},
+    instruction::Instruction,
+    keypad::{ChipKey, ChipKeypad},
+    memory::{
+        ChipMemory, BUILTIN_LARGE_SPRITES_START, MEMORY_SIZE, PROGRAM_SPACE_START, XO_MEMORY_SIZE,
+    },
     registers::ChipRegisters,
-    timers::ChipTimers,
+    rng::{FastRandSource, RandomSource},
+    state::{invalid_data, StateReader, StateWriter},
+    timers::{ChipTimers, TimerMode},
+    trace::{TraceEntry, TraceHook, Tracer},
 };
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChipVariant {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl ChipVariant {
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Self::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+}
+
+// How much Fx55/Fx65 advance I after the transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStoreQuirk {
+    Unchanged,
+    IncrementX,
+    IncrementXPlusOne,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct ChipConfig {
+    pub variant: ChipVariant,
+    // 8xy6/8xyE shift Vx in place instead of Vy
     pub modified_shift: bool,
-    pub modified_load: bool,
+    pub load_store: LoadStoreQuirk,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    // BxNN jumps to xNN + Vx instead of Bnnn jumping to nnn + V0
+    pub modified_jump: bool,
+    // Sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    // Dxyn waits for the next frame after a sprite has been drawn
+    pub display_wait: bool,
+    // Fx1E sets VF when I overflows past 0xFFF
+    pub index_overflow: bool,
+    pub timing: TimerMode,
+    // Cxkk random sequence seed, random if not set
+    pub seed: Option<u64>,
 }
 
 impl Default for ChipConfig {
     fn default() -> Self {
         Self {
+            variant: ChipVariant::Chip8,
             modified_shift: true,
-            modified_load: false,
+            load_store: LoadStoreQuirk::Unchanged,
+            vf_reset: false,
+            modified_jump: false,
+            clip_sprites: false,
+            display_wait: false,
+            index_overflow: false,
+            timing: TimerMode::WallClock,
+            seed: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    WaitingForKey,
+    WaitingForVBlank,
+    Exited,
+}
+
 #[derive(Debug, Clone)]
 pub struct ChipEmulator {
-    memory: ChipMemory,
+    pub(crate) memory: ChipMemory,
     display: ChipDisplay,
     timers: ChipTimers,
     vx: ChipRegisters,
@@ -36,9 +98,26 @@ pub struct ChipEmulator {
     sp: u8,
     stack: [u16; 16],
 
-    pressed: Option<ChipKey>,
+    // SUPER-CHIP RPL user flags, kept across resets
+    rpl: [u8; 16],
+    // XO-CHIP audio pattern buffer and pitch register
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    // F002 has run since the last reset, until then the beeper plays its own tone
+    audio_loaded: bool,
+    // A sprite has been drawn since the last timer tick
+    frame_drawn: bool,
+
+    keypad: ChipKeypad,
+    // Key pressed during Fx0A, waiting for its release
+    key_wait: Option<ChipKey>,
     config: ChipConfig,
-    rng: Rng,
+    rng: Box<dyn RandomSource>,
+    pub(crate) breakpoints: Vec<Breakpoint>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    // Instructions executed since the last reset
+    cycles: u64,
+    tracer: TraceHook,
 }
 
 impl ChipEmulator {
@@ -52,9 +131,19 @@ impl ChipEmulator {
             pc: PROGRAM_SPACE_START as u16,
             sp: 0,
             stack: [0; 16],
-            pressed: None,
+            rpl: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: 64,
+            audio_loaded: false,
+            frame_drawn: false,
+            keypad: ChipKeypad::new(),
+            key_wait: None,
             config: ChipConfig::default(),
-            rng: Rng::new(),
+            rng: Box::new(FastRandSource::new()),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            cycles: 0,
+            tracer: TraceHook::default(),
         }
     }
 
@@ -65,270 +154,727 @@ impl ChipEmulator {
     }
 
     pub fn set_config(&mut self, config: ChipConfig) {
+        let reseed = config.seed.is_some() && config.seed != self.config.seed;
+
         self.config = config;
+        self.memory.resize(config.variant.memory_size());
+        self.memory.invalidate_decoded();
+
+        if reseed {
+            self.reseed();
+        }
+    }
+
+    pub fn set_rng(&mut self, source: impl RandomSource + 'static) {
+        self.rng = Box::new(source);
+    }
+
+    fn reseed(&mut self) {
+        if let Some(seed) = self.config.seed {
+            self.rng = Box::new(FastRandSource::with_seed(seed));
+        }
+    }
+
+    // The tracer sees every instruction executed from now on
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'static) {
+        self.tracer = TraceHook(Some(Box::new(tracer)));
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.0.take()
     }
 
     pub fn load_rom<R: Read>(&mut self, r: R) -> io::Result<()> {
         self.memory.load_rom(r)
     }
 
-    pub fn current_key(&self) -> Option<&ChipKey> {
-        self.pressed.as_ref()
+    // None if PC points outside of memory
+    pub fn current_instruction(&mut self) -> Option<Result<Instruction, DecodeError>> {
+        self.memory.decode(self.pc, &self.config)
+    }
+
+    pub fn config(&self) -> &ChipConfig {
+        &self.config
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn registers(&self) -> &ChipRegisters {
+        &self.vx
+    }
+
+    pub fn registers_mut(&mut self) -> &mut ChipRegisters {
+        &mut self.vx
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn timers(&self) -> &ChipTimers {
+        &self.timers
     }
 
-    pub fn set_key(&mut self, key: Option<ChipKey>) {
-        self.pressed = key;
+    pub fn timers_mut(&mut self) -> &mut ChipTimers {
+        &mut self.timers
+    }
+
+    pub fn memory(&self) -> &ChipMemory {
+        &self.memory
+    }
+
+    // Writes through the emulator keep the decode cache up to date
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), ChipError> {
+        let end = address.checked_add(bytes.len());
+        if end.is_none_or(|end| end > self.memory.len()) {
+            return Err(ChipError::MemoryOutOfBounds {
+                pc: self.pc,
+                address: address.max(self.memory.len()),
+            });
+        }
+
+        self.memory.write(address, bytes);
+        Ok(())
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; 16] {
+        &self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: [u8; 16]) {
+        self.rpl = flags;
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn audio_loaded(&self) -> bool {
+        self.audio_loaded
+    }
+
+    pub fn keypad(&self) -> ChipKeypad {
+        self.keypad
+    }
+
+    pub fn set_keypad(&mut self, keypad: ChipKeypad) {
+        self.keypad = keypad;
+    }
+
+    pub fn press(&mut self, key: ChipKey) {
+        self.keypad.press(key);
+    }
+
+    pub fn release(&mut self, key: ChipKey) {
+        self.keypad.release(key);
+    }
+
+    pub fn is_pressed(&self, key: ChipKey) -> bool {
+        self.keypad.is_pressed(key)
     }
 
     pub fn reset(&mut self) {
         self.memory.clear();
-        self.display.clear();
+        self.display = ChipDisplay::new();
         self.timers.reset();
         self.vx.clear();
         self.i = 0;
         self.pc = PROGRAM_SPACE_START as u16;
         self.sp = 0;
         self.stack.fill(0);
+        self.audio_pattern.fill(0);
+        self.pitch = 64;
+        self.audio_loaded = false;
+        self.frame_drawn = false;
+
+        self.keypad.clear();
+        self.key_wait = None;
+        self.cycles = 0;
+        self.reseed();
+    }
+
+    pub fn save_state<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = StateWriter(w);
+
+        w.header()?;
+        w.config(&self.config)?;
+        w.block(&self.memory)?;
+
+        w.bool(self.display.hires())?;
+        w.u8(self.display.selected_planes_mask())?;
+        for plane in 0..DISPLAY_PLANES {
+            w.bytes(self.display.plane_data(plane))?;
+        }
+
+        w.bytes(self.vx.as_slice())?;
+        w.u16(self.i)?;
+        w.u16(self.pc)?;
+        w.u8(self.sp)?;
+        for addr in self.stack {
+            w.u16(addr)?;
+        }
+        w.u8(self.timers.delay)?;
+        w.u8(self.timers.sound)?;
+        w.u32(self.timers.cycles)?;
 
-        self.pressed = None;
+        w.bytes(&self.rpl)?;
+        w.bytes(&self.audio_pattern)?;
+        w.u8(self.pitch)?;
+        w.bool(self.audio_loaded)?;
+        w.bool(self.frame_drawn)?;
+
+        w.u16(self.keypad.bits())?;
+        w.u8(self.key_wait.map_or(0xFF, |key| key as u8))?;
+        w.u64(self.rng.state().unwrap_or_default())
     }
 
-    pub fn display(&self) -> DisplayIter {
+    // The emulator is left untouched if the state is invalid
+    pub fn load_state<R: Read>(&mut self, r: R) -> io::Result<()> {
+        let mut r = StateReader::new(r);
+        let mut emu = self.clone();
+
+        r.header()?;
+        emu.set_config(r.config()?);
+
+        let memory = r.block(emu.config.variant.memory_size())?;
+        if memory.len() != emu.memory.len() {
+            return Err(invalid_data("Memory size does not match variant"));
+        }
+        emu.memory.write(0, &memory);
+
+        emu.display = ChipDisplay::new();
+        emu.display.set_hires(r.bool()?);
+        emu.display.select_planes(r.u8()?);
+        for plane in 0..DISPLAY_PLANES {
+            let data = emu.display.plane_data_mut(plane);
+            r.bytes(&mut data[..PLANE_BYTES])?;
+        }
+
+        r.bytes(emu.vx.as_mut_slice())?;
+        emu.i = r.u16()?;
+        emu.pc = r.u16()?;
+        emu.sp = r.u8()?;
+        if emu.sp as usize > emu.stack.len() {
+            return Err(invalid_data("Stack pointer out of range"));
+        }
+        for addr in emu.stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+        emu.timers.reset();
+        emu.timers.delay = r.u8()?;
+        emu.timers.sound = r.u8()?;
+        emu.timers.cycles = r.u32()?;
+
+        r.bytes(&mut emu.rpl)?;
+        r.bytes(&mut emu.audio_pattern)?;
+        emu.pitch = r.u8()?;
+        emu.audio_loaded = r.bool()?;
+        emu.frame_drawn = r.bool()?;
+
+        emu.keypad = ChipKeypad::from_bits(r.u16()?);
+        emu.key_wait = match r.u8()? {
+            0xFF => None,
+            key => Some(
+                ChipKey::try_from(key)
+                    .map_err(|key| invalid_data(format!("Invalid key {}", key)))?,
+            ),
+        };
+        emu.rng.restore(r.u64()?);
+
+        // Clones don't carry the tracer over
+        emu.tracer = std::mem::take(&mut self.tracer);
+        *self = emu;
+        Ok(())
+    }
+
+    // Advances the 60 Hz timers by one tick (end of a frame)
+    pub fn tick_timers(&mut self) {
+        self.timers.step();
+        self.frame_drawn = false;
+    }
+
+    // Executes up to `instructions` instructions followed by a single timer tick.
+    // Intended for TimerMode::Manual, stops early when waiting for the next frame or on exit
+    pub fn run_frame(&mut self, instructions: u32) -> Result<StepOutcome, ChipError> {
+        let mut outcome = StepOutcome::Executed;
+
+        for _ in 0..instructions {
+            outcome = self.tick()?;
+            if matches!(outcome, StepOutcome::WaitingForVBlank | StepOutcome::Exited) {
+                break;
+            }
+        }
+
+        if outcome != StepOutcome::Exited {
+            self.tick_timers();
+        }
+
+        Ok(outcome)
+    }
+
+    pub fn display(&self) -> DisplayIter<'_> {
         self.display.iter()
     }
 
-    pub fn tick(&mut self) {
-        self.timers.tick();
+    pub fn display_size(&self) -> (usize, usize) {
+        (self.display.width(), self.display.height())
+    }
+
+    pub fn tick(&mut self) -> Result<StepOutcome, ChipError> {
+        if self.timers.tick(self.config.timing) {
+            self.frame_drawn = false;
+        }
 
-        let opcode = self.memory.fetch_opcode(self.pc);
-        let mut next_pc = self.pc + 2;
+        self.memory.clear_accesses();
 
-        match opcode.upper() & 0xF0 {
-            0x00 => match opcode.lower() {
-                // 00E0 - CLS: Clear the display
-                0xE0 => self.display.clear(),
-                //00EE - RET: Return from a subroutine
-                0xEE => {
-                    self.sp -= 1;
-                    next_pc = self.stack[self.sp as usize];
-                }
-                // 0nnn - SYS addr: Jump to a machine code routine at nnn
-                // Ignored
-                _ => (),
-            },
+        let pc = self.pc;
+        if pc as usize >= self.memory.len() {
+            return Err(ChipError::PcOutOfRange { pc });
+        }
+
+        let instruction = self
+            .memory
+            .decode(pc, &self.config)
+            .ok_or(ChipError::MemoryOutOfBounds {
+                pc,
+                address: pc as usize + 1,
+            })?
+            .map_err(|err| ChipError::InvalidOpcode {
+                pc,
+                opcode: err.opcode,
+            })?;
+
+        if self.tracer.0.is_none() {
+            let outcome = self.execute(instruction)?;
+            self.cycles += 1;
+            return Ok(outcome);
+        }
+
+        let opcode = self.memory.fetch_opcode(pc).map_or(0, |opcode| *opcode);
+        let before = self.vx.0;
+        let outcome = self.execute(instruction)?;
+
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc,
+            opcode,
+            instruction,
+            before,
+            after: self.vx.0,
+            i: self.i,
+            delay: self.timers.delay,
+            sound: self.timers.sound,
+        };
+        self.cycles += 1;
+        if let Some(tracer) = self.tracer.0.as_mut() {
+            tracer.trace(&entry);
+        }
+
+        Ok(outcome)
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, ChipError> {
+        use Instruction::*;
+
+        let pc = self.pc;
+        let mem_size = self.memory.len();
+        // None once PC would leave the 16 bit address space (XO-CHIP's 64 KiB memory)
+        let mut next_pc = pc.checked_add(2);
+
+        // Returns the memory range [start, start + len) or an out of bounds error
+        let mem_range = |start: u16, len: usize| {
+            let start = start as usize;
+            if start + len > mem_size {
+                Err(ChipError::MemoryOutOfBounds {
+                    pc,
+                    address: start.max(mem_size),
+                })
+            } else {
+                Ok(start..start + len)
+            }
+        };
+        // Skipping over XO-CHIP F000 nnnn skips the whole 4 byte instruction
+        let lookahead = self.config.variant == ChipVariant::XoChip && instruction.is_skip();
+        let skip = match next_pc.filter(|_| lookahead) {
+            Some(next)
+                if self
+                    .memory
+                    .read_opcode(next)
+                    .is_some_and(|next| *next == 0xF000) =>
+            {
+                4
+            }
+            _ => 2,
+        };
+
+        match instruction {
+            // 0nnn - SYS addr: Jump to a machine code routine at nnn
+            // Ignored
+            Sys { .. } => (),
+            // 00E0 - CLS: Clear the display
+            Cls => self.display.clear(),
+            //00EE - RET: Return from a subroutine
+            Ret => {
+                self.sp = self
+                    .sp
+                    .checked_sub(1)
+                    .ok_or(ChipError::StackUnderflow { pc })?;
+                next_pc = Some(self.stack[self.sp as usize]);
+            }
+            // 00Cn - SCD nibble: Scroll display n lines down (SUPER-CHIP)
+            ScrollDown { n } => self.display.scroll_down(n as usize),
+            // 00Dn - SCU nibble: Scroll display n lines up (XO-CHIP)
+            ScrollUp { n } => self.display.scroll_up(n as usize),
+            // 00FB - SCR: Scroll display 4 pixels right (SUPER-CHIP)
+            ScrollRight => self.display.scroll_right(4),
+            // 00FC - SCL: Scroll display 4 pixels left (SUPER-CHIP)
+            ScrollLeft => self.display.scroll_left(4),
+            // 00FD - EXIT: Exit the interpreter (SUPER-CHIP)
+            Exit => return Ok(StepOutcome::Exited),
+            // 00FE - LOW: Disable high resolution mode (SUPER-CHIP)
+            Low => self.display.set_hires(false),
+            // 00FF - HIGH: Enable high resolution mode (SUPER-CHIP)
+            High => self.display.set_hires(true),
 
             // 1nnn - JP addr: Jump to location nnn
-            0x10 => {
-                next_pc = opcode.nnn();
+            Jp { nnn } => {
+                next_pc = Some(nnn);
             }
             // 2nnn - CALL addr: Call subroutine at nnn
-            0x20 => {
-                self.stack[self.sp as usize] = next_pc;
+            Call { nnn } => {
+                *self
+                    .stack
+                    .get_mut(self.sp as usize)
+                    .ok_or(ChipError::StackOverflow { pc })? =
+                    next_pc.ok_or(ChipError::PcOutOfRange { pc })?;
                 self.sp += 1;
-                next_pc = opcode.nnn();
+                next_pc = Some(nnn);
             }
             // 3xkk - SE Vx, byte: Skip next instruction if Vx = kk
-            0x30 => {
-                if self.vx[opcode.x()] == opcode.kk() {
-                    next_pc += 2;
+            SeByte { x, kk } => {
+                if self.vx[x] == kk {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
                 }
             }
             // 4xkk - SNE Vx, byte: Skip next instruction if Vx != kk
-            0x40 => {
-                if self.vx[opcode.x()] != opcode.kk() {
-                    next_pc += 2;
+            SneByte { x, kk } => {
+                if self.vx[x] != kk {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
                 }
             }
             // 5xy0 - SE Vx, Vy: Skip next instruction if Vx = Vy
-            0x50 => {
-                if self.vx[opcode.x()] == self.vx[opcode.y()] {
-                    next_pc += 2;
+            SeReg { x, y } => {
+                if self.vx[x] == self.vx[y] {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
+                }
+            }
+            // 5xy2 - LD [I], Vx-Vy: Store registers Vx through Vy in memory starting at location I (XO-CHIP)
+            SaveRange { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let range = mem_range(self.i, x.abs_diff(y) + 1)?;
+                let regs = self.vx.as_slice();
+                let bytes = (0..range.len())
+                    .map(|offset| regs[if x <= y { x + offset } else { x - offset }])
+                    .collect::<Vec<_>>();
+
+                self.memory.write(range.start, &bytes);
+            }
+            // 5xy3 - LD Vx-Vy, [I]: Read registers Vx through Vy from memory starting at location I (XO-CHIP)
+            LoadRange { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let range = mem_range(self.i, x.abs_diff(y) + 1)?;
+                let regs = self.vx.as_mut_slice();
+
+                for (offset, byte) in self
+                    .memory
+                    .read(range.start, range.len())
+                    .iter()
+                    .enumerate()
+                {
+                    regs[if x <= y { x + offset } else { x - offset }] = *byte;
                 }
             }
             // 6xkk - LD Vx, byte: Set Vx = kk
-            0x60 => {
-                self.vx[opcode.x()] = opcode.kk();
+            LdByte { x, kk } => {
+                self.vx[x] = kk;
             }
             // 7xkk - ADD Vx, byte: Set Vx = Vx + kk
-            0x70 => {
-                self.vx[opcode.x()] = self.vx[opcode.x()].wrapping_add(opcode.kk());
+            AddByte { x, kk } => {
+                self.vx[x] = self.vx[x].wrapping_add(kk);
             }
-            0x80 => match opcode.lower() & 0x0F {
-                // 8xy0 - LD Vx, Vy: Set Vx = Vy
-                0x00 => {
-                    self.vx[opcode.x()] = self.vx[opcode.y()];
-                }
-                // 8xy1 - OR Vx, Vy: Set Vx = Vx OR Vy
-                0x01 => {
-                    self.vx[opcode.x()] |= self.vx[opcode.y()];
-                }
-                // 8xy2 - AND Vx, Vy: Set Vx = Vx AND Vy
-                0x02 => {
-                    self.vx[opcode.x()] &= self.vx[opcode.y()];
-                }
-                // 8xy3 - XOR Vx, Vy: Set Vx = Vx XOR Vy
-                0x03 => {
-                    self.vx[opcode.x()] ^= self.vx[opcode.y()];
-                }
-                // 8xy4 - ADD Vx, Vy: Set Vx = Vx + Vy, set VF = carry
-                0x04 => {
-                    let (vx, over) = self.vx[opcode.x()].overflowing_add(self.vx[opcode.y()]);
-                    self.vx[opcode.x()] = vx;
-                    self.vx[0xF] = over as u8;
-                }
-                // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow
-                0x05 => {
-                    let (vx, over) = self.vx[opcode.x()].overflowing_sub(self.vx[opcode.y()]);
-                    self.vx[opcode.x()] = vx;
-                    self.vx[0xF] = !over as u8;
-                }
-                // 8xy6 - SHR Vx {, Rhs}: Set Vx = Rhs SHR 1, set VF = LSB before shift
-                // modified_shift = false: Rhs = Vy
-                // modified_shift = true: Rhs = Vx
-                0x06 => {
-                    let rhs = self.vx[if self.config.modified_shift {
-                        opcode.x()
-                    } else {
-                        opcode.y()
-                    }];
-                    self.vx[opcode.x()] = rhs >> 1;
-                    self.vx[0xF] = rhs & 0x01;
+            // 8xy0 - LD Vx, Vy: Set Vx = Vy
+            LdReg { x, y } => {
+                self.vx[x] = self.vx[y];
+            }
+            // 8xy1 - OR Vx, Vy: Set Vx = Vx OR Vy
+            Or { x, y } => {
+                self.vx[x] |= self.vx[y];
+                if self.config.vf_reset {
+                    self.vx[0xF] = 0;
                 }
-                // 8xy7 - SUBN Vx, Vy: Set Vx = Vy - Vx, set VF = NOT borrow
-                0x07 => {
-                    let (vx, over) = self.vx[opcode.y()].overflowing_sub(self.vx[opcode.x()]);
-                    self.vx[opcode.x()] = vx;
-                    self.vx[0xF] = !over as u8;
+            }
+            // 8xy2 - AND Vx, Vy: Set Vx = Vx AND Vy
+            And { x, y } => {
+                self.vx[x] &= self.vx[y];
+                if self.config.vf_reset {
+                    self.vx[0xF] = 0;
                 }
-                // 8xyE - SHL Vx {, Rhs}: Set Vx = Rhs SHL 1, set VF = MSB before shift
-                // modified_shift = false: Rhs = Vy
-                // modified_shift = true: Rhs = Vx
-                0x0E => {
-                    let rhs = self.vx[if self.config.modified_shift {
-                        opcode.x()
-                    } else {
-                        opcode.y()
-                    }];
-                    self.vx[opcode.x()] = rhs << 1;
-                    self.vx[0xF] = rhs >> 7;
+            }
+            // 8xy3 - XOR Vx, Vy: Set Vx = Vx XOR Vy
+            Xor { x, y } => {
+                self.vx[x] ^= self.vx[y];
+                if self.config.vf_reset {
+                    self.vx[0xF] = 0;
                 }
-                _ => panic!("Invalid opcode 0x{:X}", *opcode),
-            },
+            }
+            // 8xy4 - ADD Vx, Vy: Set Vx = Vx + Vy, set VF = carry
+            AddReg { x, y } => {
+                let (vx, over) = self.vx[x].overflowing_add(self.vx[y]);
+                self.vx[x] = vx;
+                self.vx[0xF] = over as u8;
+            }
+            // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow
+            Sub { x, y } => {
+                let (vx, over) = self.vx[x].overflowing_sub(self.vx[y]);
+                self.vx[x] = vx;
+                self.vx[0xF] = !over as u8;
+            }
+            // 8xy6 - SHR Vx {, Rhs}: Set Vx = Rhs SHR 1, set VF = LSB before shift
+            // modified_shift = false: Rhs = Vy
+            // modified_shift = true: Rhs = Vx
+            Shr { x, y } => {
+                let rhs = self.vx[if self.config.modified_shift { x } else { y }];
+                self.vx[x] = rhs >> 1;
+                self.vx[0xF] = rhs & 0x01;
+            }
+            // 8xy7 - SUBN Vx, Vy: Set Vx = Vy - Vx, set VF = NOT borrow
+            Subn { x, y } => {
+                let (vx, over) = self.vx[y].overflowing_sub(self.vx[x]);
+                self.vx[x] = vx;
+                self.vx[0xF] = !over as u8;
+            }
+            // 8xyE - SHL Vx {, Rhs}: Set Vx = Rhs SHL 1, set VF = MSB before shift
+            // modified_shift = false: Rhs = Vy
+            // modified_shift = true: Rhs = Vx
+            Shl { x, y } => {
+                let rhs = self.vx[if self.config.modified_shift { x } else { y }];
+                self.vx[x] = rhs << 1;
+                self.vx[0xF] = rhs >> 7;
+            }
             // 9xy0 - SNE Vx, Vy: Skip next instruction if Vx != Vy
-            0x90 => {
-                if self.vx[opcode.x()] != self.vx[opcode.y()] {
-                    next_pc += 2;
+            SneReg { x, y } => {
+                if self.vx[x] != self.vx[y] {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
                 }
             }
 
             // Annn - LD I, addr: Set I = nnn
-            0xA0 => {
-                self.i = opcode.nnn();
+            LdI { nnn } => {
+                self.i = nnn;
             }
             // Bnnn - JP V0, addr: Jump to location nnn + V0
-            0xB0 => {
-                next_pc = opcode.nnn() + self.vx[0] as u16;
+            // modified_jump = true: BxNN - JP Vx, addr: Jump to location xNN + Vx
+            JpV0 { nnn } => {
+                let offset = self.vx[if self.config.modified_jump {
+                    (nnn >> 8) as u8
+                } else {
+                    0
+                }];
+                next_pc = Some(nnn + offset as u16);
             }
             // Cxkk - RND Vx, byte: Set Vx = random byte AND kk
-            0xC0 => {
-                self.vx[opcode.x()] = self.rng.u8(0..=255) & opcode.kk();
+            Rnd { x, kk } => {
+                self.vx[x] = self.rng.next_u8() & kk;
             }
             // Dxyn - DRW Vx, Vy, nibble: Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-            0xD0 => {
-                let coords = (self.vx[opcode.x()], self.vx[opcode.y()]);
-                let sprite = &self.memory[self.i as usize..self.i as usize + opcode.n() as usize];
+            // Dxy0 - DRW Vx, Vy, 0: Display 16x16 sprite starting at memory location I at (Vx, Vy), set VF = collision (SUPER-CHIP)
+            Drw { x, y, n } => {
+                if self.config.display_wait && self.frame_drawn {
+                    return Ok(StepOutcome::WaitingForVBlank);
+                }
 
-                let collision = self.display.draw_sprite(coords, sprite);
+                let coords = (self.vx[x], self.vx[y]);
+                let clip = self.config.clip_sprites;
 
-                self.vx[0xF] = collision as u8;
-            }
-            0xE0 => match opcode.lower() {
-                // Ex9E - SKP Vx: Skip next instruction if key with the value of Vx is pressed
-                0x9E => match self.pressed {
-                    Some(key) if key == self.vx[opcode.x()] => next_pc += 2,
-                    _ => (),
-                },
+                // XO-CHIP: sprite data for every selected plane is stored back to back
+                let planes = self.display.selected_count().max(1);
 
-                // ExA1 - SKNP Vx: Skip next instruction if key with the value of Vx is not pressed
-                0xA1 => match self.pressed {
-                    Some(key) if key != self.vx[opcode.x()] => next_pc += 2,
-                    _ => (),
-                },
+                let collision = if self.config.variant != ChipVariant::Chip8 && n == 0 {
+                    let range = mem_range(self.i, 32 * planes)?;
+                    let sprite = self.memory.read(range.start, range.len());
+                    self.display.draw_large_sprite(coords, sprite, clip)
+                } else {
+                    let range = mem_range(self.i, n as usize * planes)?;
+                    let sprite = self.memory.read(range.start, range.len());
+                    self.display.draw_sprite(coords, sprite, clip)
+                };
 
-                _ => panic!("Invalid opcode 0x{:X}", *opcode),
-            },
-            0xF0 => match opcode.lower() {
-                // Fx07 - LD Vx, DT: Set Vx = delay timer value
-                0x07 => {
-                    self.vx[opcode.x()] = self.timers.delay;
-                }
-                // Fx0A - LD Vx, K: Wait for a key press, store the value of the key in Vx
-                0x0A => match self.pressed {
-                    Some(key) => self.vx[opcode.x()] = key as u8,
-                    None => return,
-                },
-                // Fx15 - LD DT, Vx: Set delay timer = Vx
-                0x15 => {
-                    self.timers.delay = self.vx[opcode.x()];
-                }
-                // Fx18 - LD ST, Vx: Set sound timer = Vx
-                0x18 => {
-                    self.timers.sound = self.vx[opcode.x()];
-                }
-                // Fx1E - ADD I, Vx: Set I = I + Vx
-                0x1E => {
-                    self.i += self.vx[opcode.x()] as u16;
-                }
-                // Fx29 - LD F, Vx: Set I = location of sprite for digit Vx
-                0x29 => {
-                    self.i = self.vx[opcode.x()] as u16 * 5;
+                self.vx[0xF] = collision as u8;
+                self.frame_drawn = true;
+            }
+            // Ex9E - SKP Vx: Skip next instruction if key with the value of Vx is pressed
+            Skp { x } => {
+                if self.keypad.is_pressed(ChipKey::from_nibble(self.vx[x])) {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
                 }
-                // Fx33 - LD B, Vx: Store BCD representation of Vx in memory locations I, I+1, and I+2
-                0x33 => {
-                    let mut vx = self.vx[opcode.x()];
-
-                    self.memory[self.i as usize..=self.i as usize + 2]
-                        .iter_mut()
-                        .rev()
-                        .for_each(|byte| {
-                            *byte = vx % 10;
-                            vx /= 10;
-                        });
+            }
+            // ExA1 - SKNP Vx: Skip next instruction if key with the value of Vx is not pressed
+            Sknp { x } => {
+                if !self.keypad.is_pressed(ChipKey::from_nibble(self.vx[x])) {
+                    next_pc = next_pc.and_then(|next| next.checked_add(skip));
                 }
-                // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
-                // modified_load = false: Retain value of I
-                // modified_load = true: Set I = I + x + 1
-                0x55 => {
-                    self.memory[self.i as usize..=self.i as usize + opcode.x() as usize]
-                        .iter_mut()
-                        .zip(self.vx.as_slice()[..=opcode.x() as usize].iter())
-                        .for_each(|(byte, reg)| {
-                            *byte = *reg;
-                        });
-
-                    if self.config.modified_load {
-                        self.i += opcode.x() as u16 + 1;
-                    }
+            }
+
+            // F000 nnnn - LD I, long addr: Set I = nnnn (XO-CHIP)
+            LdILong => {
+                let operand = next_pc.ok_or(ChipError::PcOutOfRange { pc })?;
+                let addr =
+                    self.memory
+                        .read_opcode(operand)
+                        .ok_or(ChipError::MemoryOutOfBounds {
+                            pc,
+                            address: operand as usize + 1,
+                        })?;
+                self.i = *addr;
+                next_pc = operand.checked_add(2);
+            }
+            // Fn01 - PLANE n: Select drawing planes n (XO-CHIP)
+            Plane { n } => self.display.select_planes(n),
+            // F002 - AUDIO: Load 16 byte audio pattern buffer from memory starting at location I (XO-CHIP)
+            Audio => {
+                let range = mem_range(self.i, 16)?;
+                self.audio_pattern
+                    .copy_from_slice(self.memory.read(range.start, range.len()));
+                self.audio_loaded = true;
+            }
+            // Fx07 - LD Vx, DT: Set Vx = delay timer value
+            LdVxDt { x } => {
+                self.vx[x] = self.timers.delay;
+            }
+            // Fx0A - LD Vx, K: Wait for a key press and release, store the value of the key in Vx
+            LdVxK { x } => match self.key_wait {
+                Some(key) if !self.keypad.is_pressed(key) => {
+                    self.vx[x] = key as u8;
+                    self.key_wait = None;
                 }
-                // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
-                // modified_load = false: Retain value of I
-                // modified_load = true: Set I = I + x + 1
-                0x65 => {
-                    self.memory[self.i as usize..=self.i as usize + opcode.x() as usize]
-                        .iter()
-                        .zip(self.vx.as_mut_slice()[..=opcode.x() as usize].iter_mut())
-                        .for_each(|(byte, reg)| {
-                            *reg = *byte;
-                        });
-                    if self.config.modified_load {
-                        self.i += opcode.x() as u16 + 1;
-                    }
+                Some(_) => return Ok(StepOutcome::WaitingForKey),
+                None => {
+                    self.key_wait = self.keypad.first_pressed();
+                    return Ok(StepOutcome::WaitingForKey);
                 }
-                _ => panic!("Invalid opcode 0x{:X}", *opcode),
             },
-            _ => panic!("Invalid opcode 0x{:X}", *opcode),
+            // Fx15 - LD DT, Vx: Set delay timer = Vx
+            LdDtVx { x } => {
+                self.timers.delay = self.vx[x];
+            }
+            // Fx18 - LD ST, Vx: Set sound timer = Vx
+            LdStVx { x } => {
+                self.timers.sound = self.vx[x];
+            }
+            // Fx3A - PITCH Vx: Set audio pitch register = Vx (XO-CHIP)
+            Pitch { x } => {
+                self.pitch = self.vx[x];
+            }
+            // Fx1E - ADD I, Vx: Set I = I + Vx
+            // index_overflow = true: Set VF = I > 0xFFF
+            AddI { x } => {
+                self.i = self.i.wrapping_add(self.vx[x] as u16);
+                if self.config.index_overflow {
+                    self.vx[0xF] = (self.i > 0x0FFF) as u8;
+                }
+            }
+            // Fx29 - LD F, Vx: Set I = location of sprite for digit Vx
+            LdF { x } => {
+                self.i = self.vx[x] as u16 * 5;
+            }
+            // Fx30 - LD HF, Vx: Set I = location of large sprite for digit Vx (SUPER-CHIP)
+            LdHf { x } => {
+                self.i = BUILTIN_LARGE_SPRITES_START as u16 + self.vx[x] as u16 * 10;
+            }
+            // Fx33 - LD B, Vx: Store BCD representation of Vx in memory locations I, I+1, and I+2
+            LdB { x } => {
+                let vx = self.vx[x];
+                let range = mem_range(self.i, 3)?;
+
+                self.memory
+                    .write(range.start, &[vx / 100, vx / 10 % 10, vx % 10]);
+            }
+            // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
+            // load_store = Unchanged: Retain value of I
+            // load_store = IncrementX: Set I = I + x
+            // load_store = IncrementXPlusOne: Set I = I + x + 1
+            Store { x } => {
+                let range = mem_range(self.i, x as usize + 1)?;
+
+                self.memory
+                    .write(range.start, &self.vx.as_slice()[..=x as usize]);
+                self.i = self.i.wrapping_add(self.load_store_increment(x));
+            }
+            // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
+            // load_store = Unchanged: Retain value of I
+            // load_store = IncrementX: Set I = I + x
+            // load_store = IncrementXPlusOne: Set I = I + x + 1
+            Load { x } => {
+                let range = mem_range(self.i, x as usize + 1)?;
+
+                self.memory
+                    .read(range.start, range.len())
+                    .iter()
+                    .zip(self.vx.as_mut_slice()[..=x as usize].iter_mut())
+                    .for_each(|(byte, reg)| {
+                        *reg = *byte;
+                    });
+                self.i = self.i.wrapping_add(self.load_store_increment(x));
+            }
+            // Fx75 - LD R, Vx: Store registers V0 through Vx in RPL user flags, x <= 7 (SUPER-CHIP, any x on XO-CHIP)
+            StoreFlags { x } => {
+                let x = x as usize;
+                self.rpl[..=x].copy_from_slice(&self.vx.as_slice()[..=x]);
+            }
+            // Fx85 - LD Vx, R: Read registers V0 through Vx from RPL user flags, x <= 7 (SUPER-CHIP, any x on XO-CHIP)
+            LoadFlags { x } => {
+                let x = x as usize;
+                self.vx.as_mut_slice()[..=x].copy_from_slice(&self.rpl[..=x]);
+            }
         }
 
-        self.pc = next_pc;
+        self.pc = next_pc.ok_or(ChipError::PcOutOfRange { pc })?;
+
+        Ok(StepOutcome::Executed)
+    }
+
+    fn load_store_increment(&self, x: u8) -> u16 {
+        match self.config.load_store {
+            LoadStoreQuirk::Unchanged => 0,
+            LoadStoreQuirk::IncrementX => x as u16,
+            LoadStoreQuirk::IncrementXPlusOne => x as u16 + 1,
+        }
     }
 }
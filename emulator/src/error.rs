@@ -0,0 +1,62 @@
+use std::{error::Error, fmt, path::PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipError {
+    InvalidOpcode { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { pc: u16, address: usize },
+    PcOutOfRange { pc: u16 },
+}
+
+impl fmt::Display for ChipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOpcode { pc, opcode } => {
+                write!(f, "Invalid opcode 0x{:04X} at 0x{:03X}", opcode, pc)
+            }
+            Self::StackOverflow { pc } => write!(f, "Stack overflow at 0x{:03X}", pc),
+            Self::StackUnderflow { pc } => write!(f, "Stack underflow at 0x{:03X}", pc),
+            Self::MemoryOutOfBounds { pc, address } => write!(
+                f,
+                "Out of bounds memory access (0x{:X}) at 0x{:03X}",
+                address, pc
+            ),
+            Self::PcOutOfRange { pc } => write!(f, "Program counter out of range (0x{:X})", pc),
+        }
+    }
+}
+
+impl Error for ChipError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid opcode 0x{:04X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
@@ -0,0 +1,217 @@
+use crate::{
+    emulator::{ChipConfig, ChipVariant},
+    error::DecodeError,
+    memory::ChipOpcode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    // 0nnn - SYS addr
+    Sys { nnn: u16 },
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00Cn - SCD nibble (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    // 00Dn - SCU nibble (XO-CHIP)
+    ScrollUp { n: u8 },
+    // 00FB - SCR (SUPER-CHIP)
+    ScrollRight,
+    // 00FC - SCL (SUPER-CHIP)
+    ScrollLeft,
+    // 00FD - EXIT (SUPER-CHIP)
+    Exit,
+    // 00FE - LOW (SUPER-CHIP)
+    Low,
+    // 00FF - HIGH (SUPER-CHIP)
+    High,
+    // 1nnn - JP addr
+    Jp { nnn: u16 },
+    // 2nnn - CALL addr
+    Call { nnn: u16 },
+    // 3xkk - SE Vx, byte
+    SeByte { x: u8, kk: u8 },
+    // 4xkk - SNE Vx, byte
+    SneByte { x: u8, kk: u8 },
+    // 5xy0 - SE Vx, Vy
+    SeReg { x: u8, y: u8 },
+    // 5xy2 - LD [I], Vx-Vy (XO-CHIP)
+    SaveRange { x: u8, y: u8 },
+    // 5xy3 - LD Vx-Vy, [I] (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    // 6xkk - LD Vx, byte
+    LdByte { x: u8, kk: u8 },
+    // 7xkk - ADD Vx, byte
+    AddByte { x: u8, kk: u8 },
+    // 8xy0 - LD Vx, Vy
+    LdReg { x: u8, y: u8 },
+    // 8xy1 - OR Vx, Vy
+    Or { x: u8, y: u8 },
+    // 8xy2 - AND Vx, Vy
+    And { x: u8, y: u8 },
+    // 8xy3 - XOR Vx, Vy
+    Xor { x: u8, y: u8 },
+    // 8xy4 - ADD Vx, Vy
+    AddReg { x: u8, y: u8 },
+    // 8xy5 - SUB Vx, Vy
+    Sub { x: u8, y: u8 },
+    // 8xy6 - SHR Vx {, Vy}
+    Shr { x: u8, y: u8 },
+    // 8xy7 - SUBN Vx, Vy
+    Subn { x: u8, y: u8 },
+    // 8xyE - SHL Vx {, Vy}
+    Shl { x: u8, y: u8 },
+    // 9xy0 - SNE Vx, Vy
+    SneReg { x: u8, y: u8 },
+    // Annn - LD I, addr
+    LdI { nnn: u16 },
+    // Bnnn - JP V0, addr (BxNN - JP Vx, addr with modified_jump)
+    JpV0 { nnn: u16 },
+    // Cxkk - RND Vx, byte
+    Rnd { x: u8, kk: u8 },
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw { x: u8, y: u8, n: u8 },
+    // Ex9E - SKP Vx
+    Skp { x: u8 },
+    // ExA1 - SKNP Vx
+    Sknp { x: u8 },
+    // F000 nnnn - LD I, long addr (XO-CHIP), the address is stored in the following word
+    LdILong,
+    // Fn01 - PLANE n (XO-CHIP)
+    Plane { n: u8 },
+    // F002 - AUDIO (XO-CHIP)
+    Audio,
+    // Fx07 - LD Vx, DT
+    LdVxDt { x: u8 },
+    // Fx0A - LD Vx, K
+    LdVxK { x: u8 },
+    // Fx15 - LD DT, Vx
+    LdDtVx { x: u8 },
+    // Fx18 - LD ST, Vx
+    LdStVx { x: u8 },
+    // Fx1E - ADD I, Vx
+    AddI { x: u8 },
+    // Fx29 - LD F, Vx
+    LdF { x: u8 },
+    // Fx30 - LD HF, Vx (SUPER-CHIP)
+    LdHf { x: u8 },
+    // Fx33 - LD B, Vx
+    LdB { x: u8 },
+    // Fx3A - PITCH Vx (XO-CHIP)
+    Pitch { x: u8 },
+    // Fx55 - LD [I], Vx
+    Store { x: u8 },
+    // Fx65 - LD Vx, [I]
+    Load { x: u8 },
+    // Fx75 - LD R, Vx (SUPER-CHIP)
+    StoreFlags { x: u8 },
+    // Fx85 - LD Vx, R (SUPER-CHIP)
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    // Size of the instruction in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    // Conditionally skips the following instruction
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Self::SeByte { .. }
+                | Self::SneByte { .. }
+                | Self::SeReg { .. }
+                | Self::SneReg { .. }
+                | Self::Skp { .. }
+                | Self::Sknp { .. }
+        )
+    }
+}
+
+pub fn decode(opcode: ChipOpcode, config: &ChipConfig) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+
+    let xochip = config.variant == ChipVariant::XoChip;
+    let schip = config.variant == ChipVariant::SuperChip || xochip;
+    let (x, y, n, kk, nnn) = (
+        opcode.x(),
+        opcode.y(),
+        opcode.n(),
+        opcode.kk(),
+        opcode.nnn(),
+    );
+    let invalid = DecodeError { opcode: *opcode };
+
+    Ok(match opcode.upper() & 0xF0 {
+        0x00 => match *opcode {
+            0x00E0 => Cls,
+            0x00EE => Ret,
+            0x00C0..=0x00CF if schip => ScrollDown { n },
+            0x00D0..=0x00DF if xochip => ScrollUp { n },
+            0x00FB if schip => ScrollRight,
+            0x00FC if schip => ScrollLeft,
+            0x00FD if schip => Exit,
+            0x00FE if schip => Low,
+            0x00FF if schip => High,
+            _ => Sys { nnn },
+        },
+        0x10 => Jp { nnn },
+        0x20 => Call { nnn },
+        0x30 => SeByte { x, kk },
+        0x40 => SneByte { x, kk },
+        0x50 => match n {
+            0x0 => SeReg { x, y },
+            0x2 if xochip => SaveRange { x, y },
+            0x3 if xochip => LoadRange { x, y },
+            _ => return Err(invalid),
+        },
+        0x60 => LdByte { x, kk },
+        0x70 => AddByte { x, kk },
+        0x80 => match n {
+            0x0 => LdReg { x, y },
+            0x1 => Or { x, y },
+            0x2 => And { x, y },
+            0x3 => Xor { x, y },
+            0x4 => AddReg { x, y },
+            0x5 => Sub { x, y },
+            0x6 => Shr { x, y },
+            0x7 => Subn { x, y },
+            0xE => Shl { x, y },
+            _ => return Err(invalid),
+        },
+        0x90 => SneReg { x, y },
+        0xA0 => LdI { nnn },
+        0xB0 => JpV0 { nnn },
+        0xC0 => Rnd { x, kk },
+        0xD0 => Drw { x, y, n },
+        0xE0 => match kk {
+            0x9E => Skp { x },
+            0xA1 => Sknp { x },
+            _ => return Err(invalid),
+        },
+        _ => match kk {
+            0x00 if xochip && x == 0 => LdILong,
+            0x01 if xochip => Plane { n: x },
+            0x02 if xochip && x == 0 => Audio,
+            0x07 => LdVxDt { x },
+            0x0A => LdVxK { x },
+            0x15 => LdDtVx { x },
+            0x18 => LdStVx { x },
+            0x1E => AddI { x },
+            0x29 => LdF { x },
+            0x30 if schip => LdHf { x },
+            0x33 => LdB { x },
+            0x3A if xochip => Pitch { x },
+            0x55 => Store { x },
+            0x65 => Load { x },
+            0x75 if schip && (xochip || x <= 7) => StoreFlags { x },
+            0x85 if schip && (xochip || x <= 7) => LoadFlags { x },
+            _ => return Err(invalid),
+        },
+    })
+}
@@ -19,8 +19,107 @@ pub enum ChipKey {
     KeyF = 0xF,
 }
 
+impl ChipKey {
+    pub const ALL: [ChipKey; 16] = [
+        Self::Key0,
+        Self::Key1,
+        Self::Key2,
+        Self::Key3,
+        Self::Key4,
+        Self::Key5,
+        Self::Key6,
+        Self::Key7,
+        Self::Key8,
+        Self::Key9,
+        Self::KeyA,
+        Self::KeyB,
+        Self::KeyC,
+        Self::KeyD,
+        Self::KeyE,
+        Self::KeyF,
+    ];
+
+    // Uses only the lower nibble, like the original interpreter
+    pub fn from_nibble(value: u8) -> Self {
+        Self::ALL[(value & 0x0F) as usize]
+    }
+}
+
 impl PartialEq<u8> for ChipKey {
     fn eq(&self, other: &u8) -> bool {
         *self as u8 == *other
     }
 }
+
+impl TryFrom<u8> for ChipKey {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x0 => Self::Key0,
+            0x1 => Self::Key1,
+            0x2 => Self::Key2,
+            0x3 => Self::Key3,
+            0x4 => Self::Key4,
+            0x5 => Self::Key5,
+            0x6 => Self::Key6,
+            0x7 => Self::Key7,
+            0x8 => Self::Key8,
+            0x9 => Self::Key9,
+            0xA => Self::KeyA,
+            0xB => Self::KeyB,
+            0xC => Self::KeyC,
+            0xD => Self::KeyD,
+            0xE => Self::KeyE,
+            0xF => Self::KeyF,
+            _ => return Err(value),
+        })
+    }
+}
+
+// State of all 16 keys, one bit per key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ChipKeypad(u16);
+
+impl ChipKeypad {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn press(&mut self, key: ChipKey) {
+        self.0 |= 1 << key as u8;
+    }
+
+    pub fn release(&mut self, key: ChipKey) {
+        self.0 &= !(1 << key as u8);
+    }
+
+    pub fn set(&mut self, key: ChipKey, pressed: bool) {
+        if pressed {
+            self.press(key);
+        } else {
+            self.release(key);
+        }
+    }
+
+    pub fn is_pressed(&self, key: ChipKey) -> bool {
+        self.0 & (1 << key as u8) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
+    // Lowest pressed key, if any
+    pub fn first_pressed(&self) -> Option<ChipKey> {
+        ChipKey::ALL.into_iter().find(|key| self.is_pressed(*key))
+    }
+}
@@ -1,6 +1,19 @@
+pub mod asm;
+pub mod audio;
+pub mod debugger;
+pub mod disasm;
 pub mod display;
 pub mod emulator;
+pub mod error;
+pub mod instruction;
 pub mod keypad;
 pub mod memory;
+pub mod movie;
+pub mod platform;
 pub mod registers;
+pub mod rewind;
+pub mod rng;
+pub mod rom;
+pub mod state;
 pub mod timers;
+pub mod trace;
@@ -1,10 +1,17 @@
 use std::{
     io::{self, Read},
-    ops::{Deref, DerefMut},
+    ops::Deref,
+};
+
+use crate::{
+    emulator::ChipConfig,
+    error::DecodeError,
+    instruction::{decode, Instruction},
 };
 
 pub const PROGRAM_SPACE_START: usize = 0x200;
 pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 65536;
 pub const BUILTIN_SPRITES: [u8; 80] = [
     0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -23,6 +30,19 @@ pub const BUILTIN_SPRITES: [u8; 80] = [
     0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
     0xf0, 0x80, 0xf0, 0x80, 0x80, // F
 ];
+pub const BUILTIN_LARGE_SPRITES_START: usize = BUILTIN_SPRITES.len();
+pub const BUILTIN_LARGE_SPRITES: [u8; 100] = [
+    0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, // 1
+    0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff, // 2
+    0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c, // 3
+    0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c, // 5
+    0x3e, 0x7c, 0xc0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c, // 6
+    0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c, // 8
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c, // 9
+];
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChipOpcode(u16);
@@ -51,6 +71,12 @@ impl ChipOpcode {
     }
 }
 
+impl From<u16> for ChipOpcode {
+    fn from(opcode: u16) -> Self {
+        Self(opcode)
+    }
+}
+
 impl Deref for ChipOpcode {
     type Target = u16;
     fn deref(&self) -> &Self::Target {
@@ -58,16 +84,53 @@ impl Deref for ChipOpcode {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: usize,
+    pub len: usize,
+}
+
+impl MemoryAccess {
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.address <= end && self.address + self.len > start
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct ChipMemory([u8; MEMORY_SIZE]);
+pub struct ChipMemory {
+    data: Vec<u8>,
+    // Decoded instruction per address, dropped when the bytes under it are written
+    decoded: Vec<Option<Instruction>>,
+    // Accesses made through read / write / read_opcode / decode, only recorded while tracking is enabled
+    accesses: Option<Vec<MemoryAccess>>,
+}
 
 impl ChipMemory {
     pub fn init() -> Self {
-        let mut mem = [0; MEMORY_SIZE];
+        Self::init_sized(MEMORY_SIZE)
+    }
 
-        mem[..BUILTIN_SPRITES.len()].copy_from_slice(&BUILTIN_SPRITES);
+    pub fn init_sized(size: usize) -> Self {
+        let mut data = vec![0; size];
 
-        Self(mem)
+        data[..BUILTIN_SPRITES.len()].copy_from_slice(&BUILTIN_SPRITES);
+        data[BUILTIN_LARGE_SPRITES_START
+            ..BUILTIN_LARGE_SPRITES_START + BUILTIN_LARGE_SPRITES.len()]
+            .copy_from_slice(&BUILTIN_LARGE_SPRITES);
+
+        Self {
+            data,
+            decoded: vec![None; size],
+            accesses: None,
+        }
     }
 
     pub fn init_with<R: Read>(r: R) -> io::Result<Self> {
@@ -79,43 +142,115 @@ impl ChipMemory {
     }
 
     pub fn load_rom<R: Read>(&mut self, r: R) -> io::Result<()> {
-        for (dst, src) in self.mut_prog_space().iter_mut().zip(r.bytes()) {
+        for (dst, src) in self.data[PROGRAM_SPACE_START..].iter_mut().zip(r.bytes()) {
             *dst = src?;
         }
+        self.invalidate_decoded();
 
         Ok(())
     }
 
     pub fn clear(&mut self) {
-        self.mut_prog_space().fill(0);
+        self.data[PROGRAM_SPACE_START..].fill(0);
+        self.invalidate_decoded();
     }
 
-    pub fn fetch_opcode(&self, pc: u16) -> ChipOpcode {
-        let first = self[pc as usize];
-        let second = self[pc as usize + 1];
+    // Grows or shrinks the address space (e.g. 64 KiB for XO-CHIP)
+    pub fn resize(&mut self, size: usize) {
+        self.data.resize(size, 0);
+        self.decoded.resize(size, None);
+    }
 
-        ChipOpcode(u16::from_be_bytes([first, second]))
+    // Panics if the range is out of bounds
+    pub fn read(&mut self, address: usize, len: usize) -> &[u8] {
+        self.record(AccessKind::Read, address, len);
+        &self.data[address..address + len]
     }
 
-    pub fn prog_space(&self) -> &[u8] {
-        &self[PROGRAM_SPACE_START..]
+    // Panics if the range is out of bounds
+    pub fn write(&mut self, address: usize, bytes: &[u8]) {
+        self.data[address..address + bytes.len()].copy_from_slice(bytes);
+        self.record(AccessKind::Write, address, bytes.len());
+
+        // The instruction starting one byte earlier also covers `address`
+        let start = address.saturating_sub(1);
+        self.decoded[start..address + bytes.len()].fill(None);
     }
 
-    pub fn mut_prog_space(&mut self) -> &mut [u8] {
-        &mut self[PROGRAM_SPACE_START..]
+    pub fn write_byte(&mut self, address: usize, value: u8) {
+        self.write(address, &[value]);
     }
-}
 
-impl Deref for ChipMemory {
-    type Target = [u8; 4096];
+    pub fn fetch_opcode(&self, pc: u16) -> Option<ChipOpcode> {
+        let first = *self.get(pc as usize)?;
+        let second = *self.get(pc as usize + 1)?;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+        Some(ChipOpcode(u16::from_be_bytes([first, second])))
+    }
+
+    // fetch_opcode for operands and lookahead, recorded as a read
+    pub fn read_opcode(&mut self, address: u16) -> Option<ChipOpcode> {
+        let opcode = self.fetch_opcode(address)?;
+        self.record(AccessKind::Read, address as usize, 2);
+
+        Some(opcode)
+    }
+
+    // Decodes the instruction at `pc`, reusing the cached result until the memory is written.
+    // None if the opcode is out of bounds
+    pub fn decode(
+        &mut self,
+        pc: u16,
+        config: &ChipConfig,
+    ) -> Option<Result<Instruction, DecodeError>> {
+        let result = match self.decoded.get(pc as usize).copied().flatten() {
+            Some(instruction) => Ok(instruction),
+            None => decode(self.fetch_opcode(pc)?, config),
+        };
+        if let Ok(instruction) = result {
+            self.decoded[pc as usize] = Some(instruction);
+        }
+
+        let len = result.map_or(2, |instruction| instruction.size() as usize);
+        self.record(AccessKind::Execute, pc as usize, len);
+
+        Some(result)
+    }
+
+    pub fn set_access_tracking(&mut self, enabled: bool) {
+        self.accesses = enabled.then(Vec::new);
+    }
+
+    pub fn accesses(&self) -> &[MemoryAccess] {
+        self.accesses.as_deref().unwrap_or_default()
+    }
+
+    pub fn clear_accesses(&mut self) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.clear();
+        }
+    }
+
+    fn record(&mut self, kind: AccessKind, address: usize, len: usize) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(MemoryAccess { kind, address, len });
+        }
+    }
+
+    // Needed whenever decoding rules change, e.g. on a variant switch
+    pub fn invalidate_decoded(&mut self) {
+        self.decoded.fill(None);
+    }
+
+    pub fn prog_space(&self) -> &[u8] {
+        &self[PROGRAM_SPACE_START..]
     }
 }
 
-impl DerefMut for ChipMemory {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl Deref for ChipMemory {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
     }
 }
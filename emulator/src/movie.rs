@@ -0,0 +1,150 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    emulator::{ChipConfig, ChipEmulator},
+    keypad::ChipKeypad,
+    rom::rom_hash,
+    state::{invalid_data, StateReader, StateWriter},
+    timers::TimerMode,
+};
+
+pub const MOVIE_MAGIC: [u8; 4] = *b"R8MV";
+pub const MOVIE_VERSION: u16 = 1;
+// Upper bounds for blocks read from movie files
+const MAX_FRAMES: usize = 60 * 60 * 60 * 24;
+const MAX_STATE_SIZE: usize = 1 << 20;
+
+// Input recording of a play session. Frames run `frame_cycles` instructions with
+// TimerMode::Manual followed by a timer tick, so a replay is bit-exact
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    // Always has a seed and manual timing
+    pub config: ChipConfig,
+    pub frame_cycles: u32,
+    // Keypad state at the start of every frame
+    pub frames: Vec<u16>,
+    // Save state after the last frame, compared against when verifying a replay
+    pub final_state: Vec<u8>,
+}
+
+impl Movie {
+    // `seed` is used if the config doesn't have one
+    pub fn new(rom: &[u8], mut config: ChipConfig, frame_cycles: u32, seed: u64) -> Self {
+        config.timing = TimerMode::Manual;
+        config.seed = config.seed.or(Some(seed));
+
+        Self {
+            rom_hash: rom_hash(rom),
+            config,
+            frame_cycles: frame_cycles.max(1),
+            frames: Vec::new(),
+            final_state: Vec::new(),
+        }
+    }
+
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_hash == rom_hash(rom)
+    }
+
+    pub fn record_frame(&mut self, keypad: ChipKeypad) {
+        self.frames.push(keypad.bits());
+    }
+
+    pub fn keypad(&self, frame: usize) -> Option<ChipKeypad> {
+        self.frames.get(frame).copied().map(ChipKeypad::from_bits)
+    }
+
+    // Stores the state the recording ended in
+    pub fn finish(&mut self, emulator: &ChipEmulator) -> io::Result<()> {
+        self.final_state.clear();
+        emulator.save_state(&mut self.final_state)
+    }
+
+    // Parts of the emulator ("display", "registers") that differ from the final state
+    pub fn divergences(&self, emulator: &ChipEmulator) -> io::Result<Vec<&'static str>> {
+        let mut expected = ChipEmulator::init();
+        expected.load_state(self.final_state.as_slice())?;
+        let mut divergences = Vec::new();
+
+        if expected.display_size() != emulator.display_size()
+            || !expected.display().eq(emulator.display())
+        {
+            divergences.push("display");
+        }
+
+        let registers = |emu: &ChipEmulator| {
+            (
+                emu.registers().0,
+                emu.i(),
+                emu.pc(),
+                emu.sp(),
+                emu.stack().to_vec(),
+                emu.timers().delay,
+                emu.timers().sound,
+            )
+        };
+        if registers(&expected) != registers(emulator) {
+            divergences.push("registers");
+        }
+
+        Ok(divergences)
+    }
+
+    pub fn write_to<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = StateWriter(w);
+
+        w.bytes(&MOVIE_MAGIC)?;
+        w.u16(MOVIE_VERSION)?;
+        w.bytes(&self.rom_hash)?;
+        w.config(&self.config)?;
+        w.u32(self.frame_cycles)?;
+
+        w.u32(self.frames.len() as u32)?;
+        for keypad in &self.frames {
+            w.u16(*keypad)?;
+        }
+        w.block(&self.final_state)
+    }
+
+    pub fn read_from<R: Read>(r: R) -> io::Result<Self> {
+        let mut r = StateReader::new(r);
+
+        let mut magic = [0; 4];
+        r.bytes(&mut magic)?;
+        if magic != MOVIE_MAGIC {
+            return Err(invalid_data("Not a Rust8 movie"));
+        }
+        match r.u16()? {
+            MOVIE_VERSION => (),
+            version => {
+                return Err(invalid_data(format!(
+                    "Unsupported movie version {}",
+                    version
+                )))
+            }
+        }
+
+        let mut rom_hash = [0; 20];
+        r.bytes(&mut rom_hash)?;
+        let config = r.config()?;
+        let frame_cycles = r.u32()?;
+
+        let frame_count = r.u32()? as usize;
+        if frame_count > MAX_FRAMES {
+            return Err(invalid_data(format!("Too many frames ({})", frame_count)));
+        }
+        let frames = (0..frame_count)
+            .map(|_| r.u16())
+            .collect::<io::Result<_>>()?;
+        let final_state = r.block(MAX_STATE_SIZE)?;
+
+        Ok(Self {
+            rom_hash,
+            config,
+            frame_cycles,
+            frames,
+            final_state,
+        })
+    }
+}
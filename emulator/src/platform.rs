@@ -0,0 +1,102 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    emulator::{ChipConfig, ChipVariant, LoadStoreQuirk},
+    timers::TimerMode,
+};
+
+// Named quirk presets matching the behaviour of historical interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipPlatform {
+    CosmacVip,
+    Chip48,
+    SuperChip10,
+    SuperChip11,
+    XoChip,
+}
+
+impl ChipPlatform {
+    pub const ALL: [ChipPlatform; 5] = [
+        Self::CosmacVip,
+        Self::Chip48,
+        Self::SuperChip10,
+        Self::SuperChip11,
+        Self::XoChip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CosmacVip => "vip",
+            Self::Chip48 => "chip48",
+            Self::SuperChip10 => "schip1.0",
+            Self::SuperChip11 => "schip1.1",
+            Self::XoChip => "xochip",
+        }
+    }
+
+    pub fn config(&self) -> ChipConfig {
+        match self {
+            Self::CosmacVip => ChipConfig {
+                variant: ChipVariant::Chip8,
+                modified_shift: false,
+                load_store: LoadStoreQuirk::IncrementXPlusOne,
+                vf_reset: true,
+                modified_jump: false,
+                clip_sprites: true,
+                display_wait: true,
+                index_overflow: false,
+                timing: TimerMode::WallClock,
+                seed: None,
+            },
+            Self::Chip48 => ChipConfig {
+                variant: ChipVariant::Chip8,
+                modified_shift: true,
+                load_store: LoadStoreQuirk::IncrementX,
+                vf_reset: false,
+                modified_jump: true,
+                clip_sprites: true,
+                display_wait: false,
+                index_overflow: false,
+                timing: TimerMode::WallClock,
+                seed: None,
+            },
+            Self::SuperChip10 => ChipConfig {
+                variant: ChipVariant::SuperChip,
+                ..Self::Chip48.config()
+            },
+            Self::SuperChip11 => ChipConfig {
+                load_store: LoadStoreQuirk::Unchanged,
+                ..Self::SuperChip10.config()
+            },
+            Self::XoChip => ChipConfig {
+                variant: ChipVariant::XoChip,
+                modified_shift: false,
+                load_store: LoadStoreQuirk::IncrementXPlusOne,
+                vf_reset: false,
+                modified_jump: false,
+                clip_sprites: false,
+                display_wait: false,
+                index_overflow: false,
+                timing: TimerMode::WallClock,
+                seed: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ChipPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ChipPlatform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown platform '{}'", s))
+    }
+}
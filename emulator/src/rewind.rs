@@ -0,0 +1,265 @@
+use std::{collections::VecDeque, io};
+
+use crate::emulator::ChipEmulator;
+
+// Bounded history of save states. The newest state is kept in full, each older one as a delta
+// turning the next newer state back into it, so the oldest entry can always be dropped
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    // Maximum number of older states kept
+    capacity: usize,
+    // Frames between captures
+    interval: u32,
+    // Frames since the last capture or rewind
+    frames: u32,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Self {
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // Number of states that can be restored
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    // Called once per emulated frame, captures a state every `interval` frames
+    pub fn frame(&mut self, emulator: &ChipEmulator) -> io::Result<()> {
+        self.frames += 1;
+        if self.latest.is_none() || self.frames >= self.interval {
+            self.capture(emulator)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn capture(&mut self, emulator: &ChipEmulator) -> io::Result<()> {
+        let mut state = Vec::with_capacity(self.latest.as_ref().map_or(0, Vec::len));
+        emulator.save_state(&mut state)?;
+
+        if let Some(previous) = &self.latest {
+            self.deltas.push_back(encode_delta(&state, previous));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(state);
+        self.frames = 0;
+
+        Ok(())
+    }
+
+    // Restores the state from about `frames` frames ago, limited by the available history.
+    // Returns the number of frames actually rewound
+    pub fn rewind(&mut self, emulator: &mut ChipEmulator, frames: u32) -> io::Result<u32> {
+        let mut state = match self.latest.take() {
+            Some(state) => state,
+            None => return Ok(0),
+        };
+
+        // The latest state counts as the first step unless it was captured this frame
+        let mut rewound = self.frames;
+        let mut steps = frames.div_ceil(self.interval).max(1);
+        if self.frames > 0 {
+            steps -= 1;
+        }
+        for _ in 0..steps {
+            match self.deltas.pop_back() {
+                Some(delta) => {
+                    state = apply_delta(&state, &delta);
+                    rewound += self.interval;
+                }
+                None => break,
+            }
+        }
+
+        let result = emulator.load_state(state.as_slice());
+        // The restored state becomes the newest one
+        self.latest = Some(state);
+        self.frames = 0;
+
+        result.map(|_| rewound)
+    }
+}
+
+// XOR of `to` against `from` with unchanged runs skipped:
+// target length (u32), then [skip (u16)][count (u16)][count bytes] chunks
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| to[i] ^ from.get(i).copied().unwrap_or(0);
+    let mut delta = (to.len() as u32).to_le_bytes().to_vec();
+
+    let mut i = 0;
+    while i < to.len() {
+        let skip_start = i;
+        while i < to.len() && xor(i) == 0 && i - skip_start < u16::MAX as usize {
+            i += 1;
+        }
+        let count_start = i;
+        while i < to.len() && xor(i) != 0 && i - count_start < u16::MAX as usize {
+            i += 1;
+        }
+
+        // Trailing unchanged bytes need no chunk
+        if i == count_start && i == to.len() {
+            break;
+        }
+        delta.extend_from_slice(&((count_start - skip_start) as u16).to_le_bytes());
+        delta.extend_from_slice(&((i - count_start) as u16).to_le_bytes());
+        delta.extend((count_start..i).map(xor));
+    }
+
+    delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let len = u32::from_le_bytes([delta[0], delta[1], delta[2], delta[3]]) as usize;
+    let mut state = from.to_vec();
+    state.resize(len, 0);
+
+    let (mut position, mut i) = (0, 4);
+    while i + 4 <= delta.len() {
+        let skip = u16::from_le_bytes([delta[i], delta[i + 1]]) as usize;
+        let count = u16::from_le_bytes([delta[i + 2], delta[i + 3]]) as usize;
+        position += skip;
+        for (byte, xor) in state[position..position + count]
+            .iter_mut()
+            .zip(&delta[i + 4..i + 4 + count])
+        {
+            *byte ^= xor;
+        }
+        position += count;
+        i += 4 + count;
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{emulator::ChipConfig, timers::TimerMode};
+
+    fn round_trip(from: &[u8], to: &[u8]) {
+        let delta = encode_delta(from, to);
+        assert_eq!(apply_delta(from, &delta), to);
+    }
+
+    // ADD V0, 1 / JP 0x200, two instructions per frame so V0 counts frames
+    fn counter() -> ChipEmulator {
+        let mut emulator = ChipEmulator::with_config(ChipConfig {
+            timing: TimerMode::Manual,
+            ..ChipConfig::default()
+        });
+        emulator.load_rom(&[0x70, 0x01, 0x12, 0x00][..]).unwrap();
+        emulator
+    }
+
+    fn run(emulator: &mut ChipEmulator, buffer: &mut RewindBuffer, frames: u32) {
+        for _ in 0..frames {
+            emulator.run_frame(2).unwrap();
+            buffer.frame(emulator).unwrap();
+        }
+    }
+
+    #[test]
+    fn delta_round_trips() {
+        let state: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut changed = state.clone();
+        changed[10] ^= 1;
+        changed[500..520].fill(0xAA);
+
+        round_trip(&state, &state);
+        round_trip(&state, &changed);
+        round_trip(&changed, &state);
+        // Growing and shrinking
+        round_trip(&state[..600], &changed);
+        round_trip(&changed, &state[..600]);
+        round_trip(&[], &state);
+        round_trip(&state, &[]);
+    }
+
+    #[test]
+    fn delta_splits_long_runs() {
+        let from = vec![0; 200_000];
+        let mut to = from.clone();
+        // Unchanged and changed runs longer than a u16 chunk field
+        to[70_000] = 1;
+        to[100_000..170_000].fill(0xFF);
+
+        let delta = encode_delta(&from, &to);
+        assert_eq!(apply_delta(&from, &delta), to);
+        // The delta ends with the rest of the changed run, the unchanged tail needs no chunk
+        let rest = 70_000 - u16::MAX as usize;
+        let last_chunk = &delta[delta.len() - rest - 4..];
+        assert_eq!(last_chunk[..4], [0, 0, rest as u8, (rest >> 8) as u8]);
+
+        round_trip(&to, &from);
+    }
+
+    #[test]
+    fn rewind_restores_earlier_frames() {
+        let mut emulator = counter();
+        let mut buffer = RewindBuffer::new(10, 1);
+        run(&mut emulator, &mut buffer, 20);
+        assert_eq!(emulator.registers()[0], 20);
+
+        assert_eq!(buffer.rewind(&mut emulator, 3).unwrap(), 3);
+        assert_eq!(emulator.registers()[0], 17);
+        assert_eq!(emulator.pc(), 0x200);
+
+        // Running again continues from the restored state
+        run(&mut emulator, &mut buffer, 2);
+        assert_eq!(buffer.rewind(&mut emulator, 1).unwrap(), 1);
+        assert_eq!(emulator.registers()[0], 18);
+    }
+
+    #[test]
+    fn rewind_counts_frames_since_the_last_capture() {
+        let mut emulator = counter();
+        // Captures after frames 1, 5, 9, 13 and 17
+        let mut buffer = RewindBuffer::new(10, 4);
+        run(&mut emulator, &mut buffer, 19);
+
+        // The capture at frame 17 is the first step, 2 frames back
+        assert_eq!(buffer.rewind(&mut emulator, 1).unwrap(), 2);
+        assert_eq!(emulator.registers()[0], 17);
+
+        assert_eq!(buffer.rewind(&mut emulator, 5).unwrap(), 8);
+        assert_eq!(emulator.registers()[0], 9);
+    }
+
+    #[test]
+    fn oldest_states_are_dropped_at_capacity() {
+        let mut emulator = counter();
+        let mut buffer = RewindBuffer::new(3, 1);
+        run(&mut emulator, &mut buffer, 10);
+        assert_eq!(buffer.len(), 4);
+
+        // Only 3 older states are left, V0 = 9, 8 and 7
+        assert_eq!(buffer.rewind(&mut emulator, 100).unwrap(), 3);
+        assert_eq!(emulator.registers()[0], 7);
+        assert_eq!(buffer.len(), 1);
+
+        assert_eq!(buffer.rewind(&mut emulator, 100).unwrap(), 0);
+        assert_eq!(emulator.registers()[0], 7);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt::Debug;
+
+use fastrand::Rng;
+
+// Source of random bytes for Cxkk
+pub trait RandomSource: Debug + Send {
+    fn next_u8(&mut self) -> u8;
+
+    fn box_clone(&self) -> Box<dyn RandomSource>;
+
+    // Internal state for save states, None if the source can't be persisted
+    fn state(&self) -> Option<u64> {
+        None
+    }
+
+    fn restore(&mut self, _state: u64) {}
+}
+
+impl Clone for Box<dyn RandomSource> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct FastRandSource(Rng);
+
+// fastrand's Rng::clone advances the original and derives a different sequence,
+// copies have to continue from the same state instead
+impl Clone for FastRandSource {
+    fn clone(&self) -> Self {
+        Self(Rng::with_seed(self.0.get_seed()))
+    }
+}
+
+impl FastRandSource {
+    pub fn new() -> Self {
+        Self(Rng::new())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self(Rng::with_seed(seed))
+    }
+}
+
+impl Default for FastRandSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RandomSource for FastRandSource {
+    fn next_u8(&mut self) -> u8 {
+        self.0.u8(..)
+    }
+
+    fn box_clone(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.0.get_seed())
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.0.seed(state);
+    }
+}
+
+// Plays back a fixed sequence of bytes, wrapping around at the end
+#[derive(Debug, Clone)]
+pub struct ScriptedSource {
+    values: Vec<u8>,
+    position: usize,
+}
+
+impl ScriptedSource {
+    pub fn new(values: impl Into<Vec<u8>>) -> Self {
+        Self {
+            values: values.into(),
+            position: 0,
+        }
+    }
+}
+
+impl RandomSource for ScriptedSource {
+    fn next_u8(&mut self) -> u8 {
+        if self.values.is_empty() {
+            return 0;
+        }
+
+        let value = self.values[self.position % self.values.len()];
+        self.position = (self.position + 1) % self.values.len();
+        value
+    }
+
+    fn box_clone(&self) -> Box<dyn RandomSource> {
+        Box::new(self.clone())
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.position as u64)
+    }
+
+    fn restore(&mut self, state: u64) {
+        self.position = state as usize;
+    }
+}
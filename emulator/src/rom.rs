@@ -0,0 +1,12 @@
+// SHA-1 of the ROM image, used to identify ROMs in movies
+pub fn rom_hash(rom: &[u8]) -> [u8; 20] {
+    sha1_smol::Sha1::from(rom).digest().bytes()
+}
+
+// Lowercase hex SHA-1, as used by ROM databases
+pub fn rom_hash_hex(rom: &[u8]) -> String {
+    rom_hash(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
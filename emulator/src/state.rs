@@ -0,0 +1,194 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    emulator::{ChipConfig, ChipVariant, LoadStoreQuirk},
+    timers::TimerMode,
+};
+
+pub const STATE_MAGIC: [u8; 4] = *b"R8SS";
+pub const STATE_VERSION: u16 = 1;
+
+pub(crate) fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+// Little-endian primitive writer used by the save state format
+pub(crate) struct StateWriter<W: Write>(pub W);
+
+impl<W: Write> StateWriter<W> {
+    pub fn u8(&mut self, v: u8) -> io::Result<()> {
+        self.0.write_all(&[v])
+    }
+
+    pub fn bool(&mut self, v: bool) -> io::Result<()> {
+        self.u8(v as u8)
+    }
+
+    pub fn u16(&mut self, v: u16) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, v: u32) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, v: u64) -> io::Result<()> {
+        self.0.write_all(&v.to_le_bytes())
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) -> io::Result<()> {
+        self.0.write_all(v)
+    }
+
+    // Length prefixed byte block
+    pub fn block(&mut self, v: &[u8]) -> io::Result<()> {
+        self.u32(v.len() as u32)?;
+        self.bytes(v)
+    }
+
+    pub fn header(&mut self) -> io::Result<()> {
+        self.bytes(&STATE_MAGIC)?;
+        self.u16(STATE_VERSION)
+    }
+
+    pub fn config(&mut self, config: &ChipConfig) -> io::Result<()> {
+        self.u8(match config.variant {
+            ChipVariant::Chip8 => 0,
+            ChipVariant::SuperChip => 1,
+            ChipVariant::XoChip => 2,
+        })?;
+        self.bool(config.modified_shift)?;
+        self.u8(match config.load_store {
+            LoadStoreQuirk::Unchanged => 0,
+            LoadStoreQuirk::IncrementX => 1,
+            LoadStoreQuirk::IncrementXPlusOne => 2,
+        })?;
+        self.bool(config.vf_reset)?;
+        self.bool(config.modified_jump)?;
+        self.bool(config.clip_sprites)?;
+        self.bool(config.display_wait)?;
+        self.bool(config.index_overflow)?;
+        match config.timing {
+            TimerMode::WallClock => self.u8(0),
+            TimerMode::Cycles(n) => {
+                self.u8(1)?;
+                self.u32(n)
+            }
+            TimerMode::Manual => self.u8(2),
+        }?;
+        match config.seed {
+            Some(seed) => {
+                self.bool(true)?;
+                self.u64(seed)
+            }
+            None => self.bool(false),
+        }
+    }
+}
+
+pub(crate) struct StateReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> StateReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(invalid_data(format!("Invalid boolean value {}", v))),
+        }
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.inner.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.inner.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.inner.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)
+    }
+
+    pub fn block(&mut self, max_len: usize) -> io::Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+        if len > max_len {
+            return Err(invalid_data(format!("Block too large ({} bytes)", len)));
+        }
+
+        let mut buf = vec![0; len];
+        self.bytes(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn header(&mut self) -> io::Result<()> {
+        let mut magic = [0; 4];
+        self.bytes(&mut magic)?;
+        if magic != STATE_MAGIC {
+            return Err(invalid_data("Not a Rust8 save state"));
+        }
+
+        match self.u16()? {
+            STATE_VERSION => Ok(()),
+            version => Err(invalid_data(format!(
+                "Unsupported save state version {}",
+                version
+            ))),
+        }
+    }
+
+    pub fn config(&mut self) -> io::Result<ChipConfig> {
+        Ok(ChipConfig {
+            variant: match self.u8()? {
+                0 => ChipVariant::Chip8,
+                1 => ChipVariant::SuperChip,
+                2 => ChipVariant::XoChip,
+                v => return Err(invalid_data(format!("Invalid variant {}", v))),
+            },
+            modified_shift: self.bool()?,
+            load_store: match self.u8()? {
+                0 => LoadStoreQuirk::Unchanged,
+                1 => LoadStoreQuirk::IncrementX,
+                2 => LoadStoreQuirk::IncrementXPlusOne,
+                v => return Err(invalid_data(format!("Invalid load/store quirk {}", v))),
+            },
+            vf_reset: self.bool()?,
+            modified_jump: self.bool()?,
+            clip_sprites: self.bool()?,
+            display_wait: self.bool()?,
+            index_overflow: self.bool()?,
+            timing: match self.u8()? {
+                0 => TimerMode::WallClock,
+                1 => TimerMode::Cycles(self.u32()?),
+                2 => TimerMode::Manual,
+                v => return Err(invalid_data(format!("Invalid timer mode {}", v))),
+            },
+            seed: if self.bool()? {
+                Some(self.u64()?)
+            } else {
+                None
+            },
+        })
+    }
+}
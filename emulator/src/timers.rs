@@ -2,12 +2,25 @@ use std::time::{Duration, Instant};
 
 pub const TIMERS_TICK_RATE: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimerMode {
+    // Timers follow the host clock
+    #[default]
+    WallClock,
+    // Timers advance once every n executed instructions
+    Cycles(u32),
+    // Timers only advance through explicit frame calls
+    Manual,
+}
+
 #[derive(Debug, Clone)]
 pub struct ChipTimers {
     pub delay: u8,
     pub sound: u8,
 
     previous_tick: Option<Instant>,
+    // Instructions executed since the last tick (TimerMode::Cycles)
+    pub(crate) cycles: u32,
 }
 
 impl ChipTimers {
@@ -16,27 +29,54 @@ impl ChipTimers {
             delay: 0,
             sound: 0,
             previous_tick: None,
+            cycles: 0,
         }
     }
 
-    pub fn tick(&mut self) {
+    // Called once per executed instruction, returns whether a 60 Hz timer tick happened
+    pub fn tick(&mut self, mode: TimerMode) -> bool {
+        match mode {
+            TimerMode::WallClock => self.tick_wall_clock(),
+            TimerMode::Cycles(n) => {
+                self.cycles += 1;
+                if self.cycles >= n {
+                    self.step();
+                    true
+                } else {
+                    false
+                }
+            }
+            TimerMode::Manual => false,
+        }
+    }
+
+    // Advances both timers by exactly one 60 Hz tick
+    pub fn step(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+        self.cycles = 0;
+    }
+
+    fn tick_wall_clock(&mut self) -> bool {
         let current_tick = match self.previous_tick {
             Some(previous_tick) if previous_tick.elapsed() >= TIMERS_TICK_RATE => {
                 previous_tick + TIMERS_TICK_RATE
             }
             None => Instant::now(),
-            _ => return,
+            _ => return false,
         };
 
-        self.delay = self.delay.saturating_sub(1);
-        self.sound = self.sound.saturating_sub(1);
+        self.step();
         self.previous_tick = Some(current_tick);
+
+        true
     }
 
     pub fn reset(&mut self) {
         self.delay = 0;
         self.sound = 0;
         self.previous_tick = None;
+        self.cycles = 0;
     }
 }
 
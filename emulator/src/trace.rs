@@ -0,0 +1,54 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+
+// State around a single executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    // Instructions executed before this one
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    // V registers before and after execution
+    pub before: [u8; 16],
+    pub after: [u8; 16],
+    // I and timers after execution
+    pub i: u16,
+    pub delay: u8,
+    pub sound: u8,
+}
+
+impl TraceEntry {
+    // Registers changed by the instruction as (register, old, new)
+    pub fn deltas(&self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        (0..16u8)
+            .map(|x| (x, self.before[x as usize], self.after[x as usize]))
+            .filter(|(_, old, new)| old != new)
+    }
+}
+
+// Called after every successfully executed instruction
+pub trait Tracer: Send {
+    fn trace(&mut self, entry: &TraceEntry);
+}
+
+// Holds the emulator's tracer, clones of the emulator start without one
+#[derive(Default)]
+pub(crate) struct TraceHook(pub(crate) Option<Box<dyn Tracer>>);
+
+impl Clone for TraceHook {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl fmt::Debug for TraceHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() {
+            "TraceHook(Some(..))"
+        } else {
+            "TraceHook(None)"
+        })
+    }
+}
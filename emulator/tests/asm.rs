@@ -0,0 +1,206 @@
+use std::{fs, path::PathBuf};
+
+use rust8::{
+    asm::assemble,
+    disasm::{disassemble, disassemble_linear, Syntax},
+    emulator::ChipConfig,
+    platform::ChipPlatform,
+};
+
+const TEST_OPCODE: &[u8] = include_bytes!("../../resources/test/test_opcode.ch8");
+const BREAKOUT: &[u8] =
+    include_bytes!("../../resources/roms/Breakout (Brix hack) [David Winter, 1997].ch8");
+
+// Disassembler listing of `rom`, one mnemonic per line
+fn listing(rom: &[u8], config: &ChipConfig, linear: bool) -> String {
+    let lines = if linear {
+        disassemble_linear(rom, 0x200, config)
+    } else {
+        disassemble(rom, 0x200, config)
+    };
+
+    lines
+        .iter()
+        .map(|line| line.mnemonic(Syntax::Cowgod))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn assert_round_trip(rom: &[u8], config: &ChipConfig) {
+    for linear in [false, true] {
+        let source = listing(rom, config, linear);
+        let assembled = assemble(&source, None).unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(assembled, rom, "listing:\n{}", source);
+    }
+}
+
+// Unique directory for the include tests
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust8-asm-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn roms_round_trip_through_listing() {
+    assert_round_trip(TEST_OPCODE, &ChipConfig::default());
+    assert_round_trip(BREAKOUT, &ChipConfig::default());
+}
+
+#[test]
+fn xo_chip_source_round_trips() {
+    let source = "\
+        HIGH
+        PLANE 3
+        LD I, LONG data
+        LD [I], V0-V3
+        LD V4-V7, [I]
+        SCU 4
+        LD R, V7
+        LD V7, R
+        AUDIO
+        PITCH V2
+        JP 0x200
+    data:
+        DB 0xFF, 0x81, 0x00";
+    let rom = assemble(source, None).unwrap();
+    let config = ChipPlatform::XoChip.config();
+
+    assert_eq!(&rom[4..8], &[0xF0, 0x00, 0x02, 0x18]);
+    assert_round_trip(&rom, &config);
+}
+
+#[test]
+fn labels_resolve_forward_and_backward() {
+    let source = "\
+    start:
+        CALL sub
+        JP start
+    sub:
+        LD I, sprite
+        RET
+    sprite:
+        DB 0x3C";
+    let rom = assemble(source, None).unwrap();
+
+    assert_eq!(rom, [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0x3C]);
+}
+
+#[test]
+fn data_directives_and_constants() {
+    let source = "\
+    WIDTH EQU 8
+    AREA EQU WIDTH
+        DB 1, 0x02, #03, $04, 0b101, %110, AREA
+        DW 0x1234, end
+    end:";
+    let rom = assemble(source, None).unwrap();
+
+    assert_eq!(
+        rom,
+        [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x08, 0x12, 0x34, 0x02, 0x0B]
+    );
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let dir = temp_dir("include");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("main.asm"), "JP font\nINCLUDE \"lib/font.asm\"\n").unwrap();
+    fs::write(dir.join("lib/font.asm"), "font:\nINCLUDE \"data.asm\"\n").unwrap();
+    fs::write(dir.join("lib/data.asm"), "DB 0xF0, 0x90\n").unwrap();
+
+    let path = dir.join("main.asm");
+    let rom = assemble(&fs::read_to_string(&path).unwrap(), Some(&path));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(rom.unwrap(), [0x12, 0x02, 0xF0, 0x90]);
+}
+
+#[test]
+fn errors_report_line_and_column() {
+    let err = assemble("CLS\n  LD V0, missing", None).unwrap_err();
+    assert_eq!((err.line, err.column), (2, 10));
+    assert_eq!(err.to_string(), "2:10: Undefined symbol 'missing'");
+
+    let err = assemble("loop:\n  JP loop\nloop:", None).unwrap_err();
+    assert_eq!((err.line, err.column), (3, 1));
+
+    let err = assemble("  ADD V0, 0x100", None).unwrap_err();
+    assert_eq!((err.line, err.column), (1, 11));
+
+    let err = assemble("  FOO V0", None).unwrap_err();
+    assert_eq!((err.line, err.column), (1, 3));
+}
+
+#[test]
+fn errors_in_includes_name_the_included_file() {
+    let dir = temp_dir("include-error");
+    fs::write(dir.join("main.asm"), "CLS\nINCLUDE \"bad.asm\"\n").unwrap();
+    fs::write(dir.join("bad.asm"), "\n\n   JP V9, 0x300\n").unwrap();
+
+    let path = dir.join("main.asm");
+    let err = assemble(&fs::read_to_string(&path).unwrap(), Some(&path)).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(err.path, Some(dir.join("bad.asm")));
+    assert_eq!((err.line, err.column), (3, 4));
+}
+
+#[test]
+fn labels_past_the_address_space_are_rejected() {
+    // Fills memory from 0x200 up to the last byte
+    let source = format!("DB {}\nend:", vec!["0"; 0x10000 - 0x200].join(", "));
+    let err = assemble(&source, None).unwrap_err();
+
+    assert_eq!((err.line, err.column), (2, 1));
+    assert_eq!(
+        err.to_string(),
+        "2:1: Label address 0x10000 is out of range"
+    );
+
+    let source = format!("DB {}\nend:", vec!["0"; 0x10000 - 0x201].join(", "));
+    assert!(assemble(&source, None).is_ok());
+}
+
+#[test]
+fn operands_are_checked_against_their_field() {
+    let error = |source| assemble(source, None).unwrap_err().to_string();
+
+    assert_eq!(error("JP -1"), "1:4: Value -1 is out of range (0 to 0xFFF)");
+    assert_eq!(
+        error("LD I, 0x1000"),
+        "1:7: Value 4096 is out of range (0 to 0xFFF)"
+    );
+    assert_eq!(
+        error("DRW V0, V1, 16"),
+        "1:13: Value 16 is out of range (0 to 0xF)"
+    );
+    assert_eq!(error("SCD -1"), "1:5: Value -1 is out of range (0 to 0xF)");
+    assert_eq!(
+        error("LD V0, -129"),
+        "1:8: Value -129 is out of range (-128 to 0xFF)"
+    );
+
+    // Bytes and data may be negative
+    let rom = assemble("ADD V0, -1\nDB -128, 255\nDW -1", None).unwrap();
+    assert_eq!(rom, [0x70, 0xFF, 0x80, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn overflowing_expressions_are_errors() {
+    let error = |source| assemble(source, None).unwrap_err().to_string();
+
+    assert_eq!(
+        error("LD V0, 0x7FFFFFFFFFFFFFFF + 1"),
+        "1:8: Expression overflows"
+    );
+    assert_eq!(
+        error("BIG EQU 0x7FFFFFFFFFFFFFFF\nJP 0 - BIG - BIG"),
+        "2:4: Expression overflows"
+    );
+    assert_eq!(
+        error("LD V0, 99999999999999999999"),
+        "1:8: Invalid number '99999999999999999999'"
+    );
+}
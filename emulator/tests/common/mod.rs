@@ -0,0 +1,19 @@
+use rust8::emulator::ChipEmulator;
+
+// Top left `width` x `height` pixels of the display, '#' for lit pixels
+pub fn display_text(emulator: &ChipEmulator, width: usize, height: usize) -> String {
+    let (display_width, _) = emulator.display_size();
+    let pixels: Vec<u8> = emulator.display().collect();
+
+    pixels
+        .chunks(display_width)
+        .take(height)
+        .map(|row| {
+            row[..width]
+                .iter()
+                .map(|pixel| if *pixel != 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
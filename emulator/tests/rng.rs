@@ -0,0 +1,93 @@
+mod common;
+
+use rust8::{
+    emulator::{ChipConfig, ChipEmulator},
+    keypad::ChipKey,
+    rng::ScriptedSource,
+    timers::TimerMode,
+};
+
+const RANDOM_NUMBER_TEST: &[u8] = include_bytes!("../../resources/test/random_number_test.ch8");
+
+fn emulator(config: ChipConfig, rom: &[u8]) -> ChipEmulator {
+    let mut emulator = ChipEmulator::with_config(ChipConfig {
+        timing: TimerMode::Manual,
+        ..config
+    });
+    emulator.load_rom(rom).unwrap();
+    emulator
+}
+
+// Runs until the ROM waits for a key, then presses and releases `key`
+fn next_number(emulator: &mut ChipEmulator, key: ChipKey) {
+    emulator.run_frame(100).unwrap();
+    emulator.press(key);
+    emulator.run_frame(1).unwrap();
+    emulator.release(key);
+    emulator.run_frame(1).unwrap();
+}
+
+#[test]
+fn cxkk_masks_scripted_values() {
+    // RND V0, 0xFF / RND V1, 0x0F / RND V2, 0xF0 / RND V3, 0x55
+    let rom = [0xC0, 0xFF, 0xC1, 0x0F, 0xC2, 0xF0, 0xC3, 0x55];
+    let mut emulator = emulator(ChipConfig::default(), &rom);
+    emulator.set_rng(ScriptedSource::new([0x12, 0xAB, 0xAB, 0xFF]));
+
+    emulator.run_frame(4).unwrap();
+    assert_eq!(
+        &emulator.registers().as_slice()[..4],
+        &[0x12, 0x0B, 0xA0, 0x55]
+    );
+}
+
+#[test]
+fn random_number_test_shows_scripted_numbers() {
+    let mut emulator = emulator(ChipConfig::default(), RANDOM_NUMBER_TEST);
+    emulator.set_rng(ScriptedSource::new([123, 45]));
+
+    emulator.run_frame(100).unwrap();
+    assert_eq!(
+        common::display_text(&emulator, 15, 5),
+        "\
+..#..####.####.
+.##.....#....#.
+..#..####.####.
+..#..#.......#.
+.###.####.####."
+    );
+
+    next_number(&mut emulator, ChipKey::Key0);
+    emulator.run_frame(100).unwrap();
+    assert_eq!(
+        common::display_text(&emulator, 15, 5),
+        "\
+####.#..#.####.
+#..#.#..#.#....
+#..#.####.####.
+#..#....#....#.
+####....#.####."
+    );
+}
+
+#[test]
+fn same_seed_shows_same_numbers() {
+    let config = ChipConfig {
+        seed: Some(1234),
+        ..ChipConfig::default()
+    };
+    let displays: Vec<Vec<Vec<u8>>> = (0..2)
+        .map(|_| {
+            let mut emulator = emulator(config, RANDOM_NUMBER_TEST);
+            (0..10)
+                .map(|_| {
+                    next_number(&mut emulator, ChipKey::Key0);
+                    emulator.display().collect()
+                })
+                .collect()
+        })
+        .collect();
+
+    assert_eq!(displays[0], displays[1]);
+    assert!(displays[0].iter().any(|display| *display != displays[0][0]));
+}
@@ -0,0 +1,126 @@
+use rust8::{
+    emulator::{ChipConfig, ChipEmulator},
+    keypad::ChipKey,
+    platform::ChipPlatform,
+    timers::TimerMode,
+};
+
+const BREAKOUT: &[u8] =
+    include_bytes!("../../resources/roms/Breakout (Brix hack) [David Winter, 1997].ch8");
+// Bytes saved after the stack pointer: stack, timers, RPL flags, audio, keypad and RNG state
+const STATE_AFTER_SP: usize = 32 + 1 + 1 + 4 + 16 + 16 + 1 + 1 + 1 + 2 + 1 + 8;
+
+// Everything a save state should restore
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    registers: Vec<u8>,
+    i: u16,
+    pc: u16,
+    sp: u8,
+    stack: Vec<u16>,
+    memory: Vec<u8>,
+    display: Vec<u8>,
+    timers: (u8, u8),
+    keypad: u16,
+}
+
+fn snapshot(emulator: &ChipEmulator) -> Snapshot {
+    Snapshot {
+        registers: emulator.registers().as_slice().to_vec(),
+        i: emulator.i(),
+        pc: emulator.pc(),
+        sp: emulator.sp(),
+        stack: emulator.stack().to_vec(),
+        memory: emulator.memory().to_vec(),
+        display: emulator.display().collect(),
+        timers: (emulator.timers().delay, emulator.timers().sound),
+        keypad: emulator.keypad().bits(),
+    }
+}
+
+fn save(emulator: &ChipEmulator) -> Vec<u8> {
+    let mut state = Vec::new();
+    emulator.save_state(&mut state).unwrap();
+    state
+}
+
+// Breakout a few frames in, with the paddle moving left
+fn running_emulator() -> ChipEmulator {
+    let mut emulator = ChipEmulator::with_config(ChipConfig {
+        timing: TimerMode::Manual,
+        seed: Some(7),
+        ..ChipPlatform::CosmacVip.config()
+    });
+    emulator.load_rom(BREAKOUT).unwrap();
+    for _ in 0..30 {
+        emulator.run_frame(15).unwrap();
+    }
+    emulator.press(ChipKey::Key4);
+    emulator.run_frame(15).unwrap();
+    emulator
+}
+
+#[test]
+fn state_round_trips() {
+    let mut emulator = running_emulator();
+    let saved = snapshot(&emulator);
+    let state = save(&emulator);
+
+    emulator.release(ChipKey::Key4);
+    emulator.press(ChipKey::Key6);
+    for _ in 0..20 {
+        emulator.run_frame(15).unwrap();
+    }
+    emulator.registers_mut()[3] ^= 0xFF;
+    emulator.write_memory(0x300, &[0xAA; 8]).unwrap();
+    assert_ne!(snapshot(&emulator), saved);
+
+    emulator.load_state(&state[..]).unwrap();
+    assert_eq!(snapshot(&emulator), saved);
+    assert_eq!(save(&emulator), state);
+
+    // The RNG continues where it was saved
+    let mut fresh = running_emulator();
+    for _ in 0..60 {
+        emulator.run_frame(15).unwrap();
+        fresh.run_frame(15).unwrap();
+    }
+    assert_eq!(snapshot(&emulator), snapshot(&fresh));
+}
+
+#[test]
+fn truncated_state_leaves_emulator_untouched() {
+    let mut emulator = running_emulator();
+    let state = save(&emulator);
+
+    let mut other = running_emulator();
+    other.release(ChipKey::Key4);
+    other.run_frame(15).unwrap();
+    let before = snapshot(&other);
+    let saved_before = save(&other);
+
+    for len in [0, 6, state.len() / 2, state.len() - 1] {
+        assert!(other.load_state(&state[..len]).is_err());
+        assert_eq!(snapshot(&other), before);
+        assert_eq!(save(&other), saved_before);
+    }
+
+    emulator.load_state(&saved_before[..]).unwrap();
+    assert_eq!(snapshot(&emulator), before);
+}
+
+#[test]
+fn invalid_stack_pointer_leaves_emulator_untouched() {
+    let mut emulator = running_emulator();
+    let mut state = save(&emulator);
+    let sp = state.len() - STATE_AFTER_SP - 1;
+    assert_eq!(state[sp], emulator.sp());
+
+    emulator.run_frame(15).unwrap();
+    let before = snapshot(&emulator);
+
+    state[sp] = 17;
+    let err = emulator.load_state(&state[..]).unwrap_err();
+    assert_eq!(err.to_string(), "Stack pointer out of range");
+    assert_eq!(snapshot(&emulator), before);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use rust8::{
+    emulator::{ChipConfig, ChipEmulator},
+    keypad::ChipKey,
+    timers::TimerMode,
+};
+
+const DELAY_TIMER_TEST: &[u8] = include_bytes!("../../resources/test/delay_timer_test.ch8");
+// Instructions per 60 Hz frame
+const IPF: u32 = 20;
+
+fn emulator(timing: TimerMode) -> ChipEmulator {
+    let mut emulator = ChipEmulator::with_config(ChipConfig {
+        timing,
+        ..ChipConfig::default()
+    });
+    emulator.load_rom(DELAY_TIMER_TEST).unwrap();
+    emulator
+}
+
+fn run_frames(emulator: &mut ChipEmulator, frames: u32) {
+    for _ in 0..frames {
+        match emulator.config().timing {
+            TimerMode::Manual => {
+                emulator.run_frame(IPF).unwrap();
+            }
+            _ => {
+                for _ in 0..IPF {
+                    emulator.tick().unwrap();
+                }
+            }
+        }
+    }
+}
+
+// Fx0A needs the key to be pressed and released
+fn press(emulator: &mut ChipEmulator, key: ChipKey) {
+    emulator.press(key);
+    run_frames(emulator, 2);
+    emulator.release(key);
+    run_frames(emulator, 2);
+}
+
+// Key 8 decrements the start value from 0 to 255, key 5 starts the countdown
+fn start_countdown(timing: TimerMode) -> ChipEmulator {
+    let mut emulator = emulator(timing);
+    run_frames(&mut emulator, 5);
+    press(&mut emulator, ChipKey::Key8);
+    press(&mut emulator, ChipKey::Key5);
+    emulator
+}
+
+#[test]
+fn manual_frames_count_down_delay_timer() {
+    let mut emulator = start_countdown(TimerMode::Manual);
+    assert_eq!(emulator.timers().delay, 253);
+
+    run_frames(&mut emulator, 100);
+    assert_eq!(emulator.timers().delay, 153);
+    assert_eq!(emulator.registers()[6], 154);
+    assert_eq!(
+        common::display_text(&emulator, 15, 5),
+        "\
+..#..####.#..#.
+.##..#....#..#.
+..#..####.####.
+..#.....#....#.
+.###.####....#."
+    );
+
+    run_frames(&mut emulator, 200);
+    assert_eq!(emulator.timers().delay, 0);
+    assert_eq!(emulator.registers()[6], 0);
+}
+
+#[test]
+fn cycle_timing_counts_down_delay_timer() {
+    let mut emulator = start_countdown(TimerMode::Cycles(IPF));
+    assert_eq!(emulator.timers().delay, 253);
+
+    run_frames(&mut emulator, 100);
+    assert_eq!(emulator.timers().delay, 153);
+    // The timer ticks before the frame's last instruction, which reads it before it is drawn
+    assert_eq!(emulator.registers()[6], 153);
+    assert_eq!(
+        common::display_text(&emulator, 15, 5),
+        "\
+..#..####.#..#.
+.##..#....#..#.
+..#..####.####.
+..#.....#....#.
+.###.####....#."
+    );
+
+    run_frames(&mut emulator, 200);
+    assert_eq!(emulator.timers().delay, 0);
+    assert_eq!(emulator.registers()[6], 0);
+}
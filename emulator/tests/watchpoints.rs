@@ -0,0 +1,77 @@
+use rust8::{
+    debugger::{StopReason, Watchpoint},
+    emulator::ChipEmulator,
+    memory::{AccessKind, MemoryAccess},
+    platform::ChipPlatform,
+};
+
+fn xo_chip(rom: &[u8]) -> ChipEmulator {
+    let mut emulator = ChipEmulator::with_config(ChipPlatform::XoChip.config());
+    emulator.load_rom(rom).unwrap();
+    emulator
+}
+
+fn watch_reads(emulator: &mut ChipEmulator, start: u16, end: u16) {
+    emulator.add_watchpoint(Watchpoint {
+        start,
+        end,
+        read: true,
+        write: false,
+        execute: false,
+    });
+}
+
+// Access that stopped the run, with the PC of the instruction that made it
+fn hit(reason: StopReason) -> (u16, MemoryAccess) {
+    match reason {
+        StopReason::Watchpoint(hit) => (hit.pc, hit.access),
+        reason => panic!("Expected a watchpoint, stopped with {:?}", reason),
+    }
+}
+
+#[test]
+fn skip_lookahead_is_a_read() {
+    // SE V0, 0 skips the whole F000 0300 that follows
+    let mut emulator = xo_chip(&[0x30, 0x00, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x06]);
+    watch_reads(&mut emulator, 0x202, 0x203);
+
+    let (pc, access) = hit(emulator.resume(100).unwrap());
+    assert_eq!(pc, 0x200);
+    assert_eq!(
+        access,
+        MemoryAccess {
+            kind: AccessKind::Read,
+            address: 0x202,
+            len: 2
+        }
+    );
+    assert_eq!(emulator.pc(), 0x206);
+}
+
+#[test]
+fn long_load_operand_is_a_read() {
+    let mut emulator = xo_chip(&[0xF0, 0x00, 0x12, 0x34, 0x12, 0x04]);
+    watch_reads(&mut emulator, 0x203, 0x203);
+
+    let (pc, access) = hit(emulator.resume(100).unwrap());
+    assert_eq!(pc, 0x200);
+    assert_eq!(
+        access,
+        MemoryAccess {
+            kind: AccessKind::Read,
+            address: 0x202,
+            len: 2
+        }
+    );
+    assert_eq!(emulator.i(), 0x1234);
+}
+
+#[test]
+fn only_skips_look_ahead() {
+    // LD V0, 0 doesn't read the F000 that follows, which executes without reading itself
+    let mut emulator = xo_chip(&[0x60, 0x00, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x06]);
+    watch_reads(&mut emulator, 0x202, 0x203);
+
+    assert_eq!(emulator.resume(100).unwrap(), StopReason::CycleLimit);
+    assert_eq!(emulator.i(), 0x300);
+}
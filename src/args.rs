@@ -1,51 +1,419 @@
 use std::path::PathBuf;
 
-use clap::{crate_authors, crate_version, value_parser, Arg, ArgMatches, Command};
+use clap::{
+    builder::PossibleValuesParser, crate_authors, crate_version, value_parser, Arg, ArgAction,
+    ArgMatches, Command, ValueSource,
+};
+use rust8::{
+    emulator::{ChipConfig, ChipVariant, LoadStoreQuirk},
+    platform::ChipPlatform,
+    timers::TimerMode,
+};
+
+use crate::{config::Settings, palette::parse_palette};
 
 pub fn parse_args() -> ArgMatches {
+    command().get_matches()
+}
+
+pub fn command() -> Command<'static> {
     Command::new("Rust8")
         .version(crate_version!())
         .author(crate_authors!())
         .about("Rust8 is a CHIP-8 interpreter")
-        .arg(
-            Arg::new("rom")
-                .help("Path to the ROM to load")
-                .required(false)
-                .value_name("ROM")
-                .value_parser(value_parser!(PathBuf)),
+        .args_conflicts_with_subcommands(true)
+        .arg(rom_arg().required(false))
+        .args(emulator_args())
+        .arg(gdb_arg().requires("rom"))
+        .args(movie_args().map(|arg| arg.requires("rom")))
+        .args(audio_args())
+        .args(frontend_args())
+        .mut_arg("tui", |arg| arg.requires("rom"))
+        .subcommand(
+            Command::new("run")
+                .about("Run a ROM, optionally without opening a window")
+                .arg(rom_arg().required(true))
+                .args(emulator_args())
+                .arg(
+                    Arg::new("headless")
+                        .long("headless")
+                        .help("Run without opening a window")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(gdb_arg().conflicts_with("headless"))
+                .args(movie_args())
+                .args(audio_args())
+                .args(frontend_args())
+                .mut_arg("keys", |arg| arg.conflicts_with("headless"))
+                .mut_arg("phosphor_decay", |arg| arg.conflicts_with("headless"))
+                .mut_arg("tui", |arg| arg.conflicts_with("headless"))
+                .mut_arg("record", |arg| arg.conflicts_with("cycles"))
+                .mut_arg("replay", |arg| {
+                    arg.conflicts_with_all(&["input", "cycles", "frames"])
+                })
+                .arg(
+                    Arg::new("cycles")
+                        .long("cycles")
+                        .value_name("COUNT")
+                        .help("Stop after executing COUNT instructions")
+                        .requires("headless")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("frames")
+                        .long("frames")
+                        .value_name("COUNT")
+                        .help("Stop after COUNT frames (60 Hz timer ticks)")
+                        .requires("headless")
+                        .conflicts_with("cycles")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .value_name("SCRIPT")
+                        .help("Feed key presses from SCRIPT (lines of '<frame> +<key>' or '<frame> -<key>')")
+                        .requires("headless")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("dump_display")
+                        .long("dump-display")
+                        .value_name("FORMAT")
+                        .help("Dump the final display as ASCII art or PNG")
+                        .requires("headless")
+                        .default_value("ascii")
+                        .value_parser(["ascii", "png", "none"]),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("PATH")
+                        .help("Write the display dump to PATH instead of stdout")
+                        .requires("headless")
+                        .required_if_eq("dump_display", "png")
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("registers")
+                        .long("registers")
+                        .value_name("PATH")
+                        .help("Dump the final register state as JSON to PATH ('-' for stdout)")
+                        .requires("headless")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("disasm")
+                .about("Disassemble a ROM")
+                .arg(rom_arg().required(true))
+                .args(variant_args())
+                .arg(
+                    Arg::new("syntax")
+                        .long("syntax")
+                        .value_name("SYNTAX")
+                        .help("Mnemonic style of the listing")
+                        .default_value("cowgod")
+                        .value_parser(["cowgod", "octo"]),
+                )
+                .arg(
+                    Arg::new("linear")
+                        .long("linear")
+                        .help("Decode every word instead of following control flow from 0x200")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("debug")
+                .about("Debug a ROM in an interactive terminal prompt")
+                .arg(rom_arg().required(true))
+                .args(emulator_args())
+                .arg(config_arg())
+                .arg(database_arg()),
         )
-        .arg(
-            Arg::new("clock_speed")
-                .short('c')
-                .long("clock-speed")
-                .value_name("SPEED")
-                .help("Set cpu clock speed (in Hz)")
-                .default_value("700")
-                .value_parser(value_parser!(u32)),
+        .subcommand(
+            Command::new("info")
+                .about("Show what the ROM database knows about a ROM")
+                .arg(rom_arg().required(true))
+                .arg(database_arg()),
         )
-        .arg(
-            Arg::new("modified_shift")
-                .short('s')
-                .long("-use-modified-shift")
-                .help("Use modified 8XY6/8XYE operation (shift VX, not VY)")
-                .default_value("true")
-                .min_values(0)
-                .value_name("bool")
-                .require_equals(true)
-                .default_missing_value("true")
-                .value_parser(value_parser!(bool)),
+        .subcommand(
+            Command::new("asm")
+                .about("Assemble a source file into a ROM")
+                .arg(
+                    Arg::new("source")
+                        .help("Path to the assembly source")
+                        .value_name("SOURCE")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("PATH")
+                        .help("Write the ROM to PATH instead of SOURCE with a .ch8 extension")
+                        .value_parser(value_parser!(PathBuf)),
+                ),
         )
-        .arg(
-            Arg::new("modified_load")
-                .short('l')
-                .long("-use-modified-load")
-                .help("Use modified FX55/FX65 operation (don't increase I by X + 1)")
-                .default_value("false")
-                .min_values(0)
-                .value_name("bool")
-                .require_equals(true)
-                .default_missing_value("true")
-                .value_parser(value_parser!(bool)),
+}
+
+fn rom_arg() -> Arg<'static> {
+    Arg::new("rom")
+        .help("Path to the ROM to load")
+        .value_name("ROM")
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn gdb_arg() -> Arg<'static> {
+    Arg::new("gdb")
+        .long("gdb")
+        .value_name("ADDRESS")
+        .help(
+            "Wait for a GDB remote connection on ADDRESS (e.g. 127.0.0.1:9001) instead of running",
         )
-        .get_matches()
+        .conflicts_with_all(&["record", "replay"])
+}
+
+fn movie_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("record")
+            .long("record")
+            .value_name("PATH")
+            .help("Record keypad input to a movie file")
+            .value_parser(value_parser!(PathBuf)),
+        Arg::new("replay")
+            .long("replay")
+            .value_name("PATH")
+            .help("Replay a movie file, headless runs fail if the final state diverges")
+            .conflicts_with("record")
+            .value_parser(value_parser!(PathBuf)),
+    ]
+}
+
+fn config_arg() -> Arg<'static> {
+    Arg::new("config")
+        .long("config")
+        .value_name("PATH")
+        .help("Read key bindings and defaults from PATH instead of the user config file")
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn database_arg() -> Arg<'static> {
+    Arg::new("database")
+        .long("database")
+        .value_name("PATH")
+        .help("Look up ROMs in a chip-8-database programs.json at PATH instead of the bundled one")
+        .value_parser(value_parser!(PathBuf))
+}
+
+// Options of the interactive frontends, --palette also applies to PNG dumps
+fn frontend_args() -> [Arg<'static>; 6] {
+    [
+        Arg::new("tui")
+            .long("tui")
+            .help("Render to the terminal instead of opening a window")
+            .conflicts_with("gdb")
+            .action(ArgAction::SetTrue),
+        Arg::new("keys")
+            .long("keys")
+            .value_name("KEYS")
+            .help("Keys for the keypad rows 123C 456D 789E A0BF (e.g. 1234qwerasdfzxcv)"),
+        config_arg(),
+        database_arg(),
+        Arg::new("palette")
+            .long("palette")
+            .value_name("PALETTE")
+            .help(
+                "Display colours: default, green, amber, lcd or 2 to 4 colours starting with the background (e.g. 001a00,33ff66)",
+            )
+            .value_parser(parse_palette),
+        Arg::new("phosphor_decay")
+            .long("phosphor-decay")
+            .value_name("FACTOR")
+            .help("Brightness unlit pixels keep per frame, between 0.0 (no ghosting) and 1.0")
+            .value_parser(value_parser!(f32)),
+    ]
+}
+
+fn audio_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("mute")
+            .long("mute")
+            .help("Disable sound")
+            .action(ArgAction::SetTrue),
+        Arg::new("wav")
+            .long("wav")
+            .value_name("PATH")
+            .help("Write the sound output to a WAV file instead of the sound card")
+            .conflicts_with("mute")
+            .value_parser(value_parser!(PathBuf)),
+        Arg::new("waveform")
+            .long("waveform")
+            .value_name("WAVEFORM")
+            .help("Waveform of the beeper")
+            .default_value("square")
+            .value_parser(["square", "sine"]),
+        Arg::new("frequency")
+            .long("frequency")
+            .value_name("HZ")
+            .help("Frequency of the beeper")
+            .default_value("440")
+            .value_parser(value_parser!(f32)),
+        Arg::new("volume")
+            .long("volume")
+            .value_name("VOLUME")
+            .help("Volume of the beeper, between 0.0 and 1.0")
+            .default_value("0.25")
+            .value_parser(value_parser!(f32)),
+    ]
+}
+
+fn variant_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("platform")
+            .short('p')
+            .long("platform")
+            .value_name("PLATFORM")
+            .help("Use instruction set and quirks of given platform")
+            .value_parser(PossibleValuesParser::new(
+                ChipPlatform::ALL.map(|platform| platform.name()),
+            )),
+        Arg::new("variant")
+            .long("variant")
+            .value_name("VARIANT")
+            .help("Override instruction set variant")
+            .value_parser(["chip8", "schip", "xochip"]),
+    ]
+}
+
+fn emulator_args() -> Vec<Arg<'static>> {
+    let mut args = vec![
+        Arg::new("clock_speed")
+            .short('c')
+            .long("clock-speed")
+            .value_name("SPEED")
+            .help("Set cpu clock speed (in Hz)")
+            .default_value("700")
+            .value_parser(value_parser!(u32)),
+        Arg::new("instructions_per_frame")
+            .long("ipf")
+            .value_name("COUNT")
+            .help("Advance timers every COUNT instructions instead of following the wall clock")
+            .value_parser(value_parser!(u32).range(1..)),
+        Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("Seed the random number generator used by CXKK")
+            .value_parser(value_parser!(u64)),
+        Arg::new("modified_shift")
+            .short('s')
+            .long("-use-modified-shift")
+            .help("Use modified 8XY6/8XYE operation (shift VX, not VY)")
+            .min_values(0)
+            .value_name("bool")
+            .require_equals(true)
+            .default_missing_value("true")
+            .value_parser(value_parser!(bool)),
+        Arg::new("modified_load")
+            .short('l')
+            .long("-use-modified-load")
+            .help("Use modified FX55/FX65 operation (don't increase I by X + 1)")
+            .min_values(0)
+            .value_name("bool")
+            .require_equals(true)
+            .default_missing_value("true")
+            .value_parser(value_parser!(bool)),
+        Arg::new("trace")
+            .long("trace")
+            .value_name("PATH")
+            .help("Log every executed instruction to PATH")
+            .value_parser(value_parser!(PathBuf)),
+        Arg::new("trace_format")
+            .long("trace-format")
+            .value_name("FORMAT")
+            .help("Format of the trace log")
+            .requires("trace")
+            .default_value("text")
+            .value_parser(["text", "jsonl", "binary"]),
+        Arg::new("trace_range")
+            .long("trace-range")
+            .value_name("START-END")
+            .help("Only trace instructions with PC in START-END (hex, e.g. 0x200-0x2FF)")
+            .requires("trace"),
+        Arg::new("trace_opcodes")
+            .long("trace-opcodes")
+            .value_name("CLASSES")
+            .help("Only trace opcodes starting with one of CLASSES (hex nibbles, e.g. D,F)")
+            .requires("trace"),
+    ];
+    args.extend(variant_args());
+    args
+}
+
+// Platform preset and variant override only, for subcommands that don't run the ROM
+pub fn variant_config(args: &ArgMatches) -> ChipConfig {
+    preset_config(args, None)
+}
+
+// `platform` is used without a --platform option
+fn preset_config(args: &ArgMatches, platform: Option<ChipPlatform>) -> ChipConfig {
+    let mut config = args
+        .get_one::<String>("platform")
+        .map(|platform| {
+            platform
+                .parse::<ChipPlatform>()
+                .expect("Platform should be validated by clap")
+        })
+        .or(platform)
+        .map(|platform| platform.config())
+        .unwrap_or_default();
+
+    if let Some(variant) = args.get_one::<String>("variant") {
+        config.variant = match variant.as_str() {
+            "schip" => ChipVariant::SuperChip,
+            "xochip" => ChipVariant::XoChip,
+            _ => ChipVariant::Chip8,
+        };
+    }
+
+    config
+}
+
+// Command line options take precedence over the config file and ROM database settings
+pub fn configured_chip_config(args: &ArgMatches, settings: &Settings) -> ChipConfig {
+    let mut config = preset_config(args, settings.platform);
+    settings.apply(&mut config);
+
+    if let Some(seed) = args.get_one::<u64>("seed") {
+        config.seed = Some(*seed);
+    }
+    if let Some(ipf) = args.get_one::<u32>("instructions_per_frame") {
+        config.timing = TimerMode::Cycles((*ipf).max(1));
+    }
+    if let Some(modified_shift) = args.get_one::<bool>("modified_shift") {
+        config.modified_shift = *modified_shift;
+    }
+    if let Some(modified_load) = args.get_one::<bool>("modified_load") {
+        config.load_store = if *modified_load {
+            LoadStoreQuirk::Unchanged
+        } else {
+            LoadStoreQuirk::IncrementXPlusOne
+        };
+    }
+
+    config
+}
+
+// --clock-speed if given, otherwise the config file, the ROM database or the default
+pub fn clock_speed(args: &ArgMatches, settings: &Settings) -> u32 {
+    let clock_speed = *args
+        .get_one::<u32>("clock_speed")
+        .expect("Clock speed should have default value");
+
+    match (args.value_source("clock_speed"), settings.clock_speed) {
+        (Some(ValueSource::DefaultValue), Some(configured)) => configured,
+        _ => clock_speed,
+    }
 }
@@ -0,0 +1,24 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::asm;
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let source_path = args
+        .get_one::<PathBuf>("source")
+        .expect("Source is required");
+    let output = args
+        .get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| source_path.with_extension("ch8"));
+
+    let source = fs::read_to_string(source_path)
+        .with_context(|| format!("Failed to read {}", source_path.display()))?;
+    let rom = asm::assemble(&source, Some(source_path))?;
+
+    fs::write(&output, &rom).with_context(|| format!("Failed to write {}", output.display()))?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    audio::{AudioSink, Beeper, BeeperConfig, Waveform},
+    emulator::ChipEmulator,
+};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+// Discards all samples, used when there's no sound card
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn write(&mut self, _samples: &[f32]) {}
+}
+
+// Writes 16 bit mono PCM, the header sizes are filled in when the sink is dropped
+pub struct WavSink {
+    writer: BufWriter<File>,
+    data_len: u32,
+}
+
+impl WavSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, 1 channel
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        // Block align and bits per sample
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn write(&mut self, samples: &[f32]) {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if let Err(err) = self.writer.write_all(&value.to_le_bytes()) {
+                eprintln!("Failed to write audio: {}", err);
+                return;
+            }
+            self.data_len += 2;
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Failed to finish WAV file: {}", err);
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+use device::DeviceSink;
+
+#[cfg(feature = "audio")]
+mod device {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::{Context, Result};
+    use cpal::{
+        traits::{DeviceTrait, HostTrait, StreamTrait},
+        Device, Sample, SampleFormat, Stream, StreamConfig,
+    };
+    use rust8::audio::AudioSink;
+
+    type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+    // Default output device of the system, samples are queued for the audio thread
+    pub struct DeviceSink {
+        _stream: Stream,
+        queue: SampleQueue,
+        sample_rate: u32,
+    }
+
+    impl DeviceSink {
+        pub fn open() -> Result<Self> {
+            let device = cpal::default_host()
+                .default_output_device()
+                .context("No audio output device available")?;
+            let supported = device.default_output_config()?;
+            let format = supported.sample_format();
+            let config: StreamConfig = supported.into();
+
+            let queue = SampleQueue::default();
+            let stream = match format {
+                SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone()),
+                SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone()),
+                SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone()),
+            }?;
+            stream.play()?;
+
+            Ok(Self {
+                _stream: stream,
+                queue,
+                sample_rate: config.sample_rate.0,
+            })
+        }
+    }
+
+    impl AudioSink for DeviceSink {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn write(&mut self, samples: &[f32]) {
+            let mut queue = self.queue.lock().expect("Audio queue poisoned");
+            queue.extend(samples);
+
+            // Drop the oldest samples to keep the latency below 100 ms
+            let max_len = self.sample_rate as usize / 10;
+            if queue.len() > max_len {
+                let excess = queue.len() - max_len;
+                queue.drain(..excess);
+            }
+        }
+    }
+
+    fn build_stream<T: Sample>(
+        device: &Device,
+        config: &StreamConfig,
+        queue: SampleQueue,
+    ) -> Result<Stream> {
+        let channels = config.channels as usize;
+
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().expect("Audio queue poisoned");
+                for frame in data.chunks_mut(channels) {
+                    let sample = queue.pop_front().unwrap_or(0.0);
+                    frame.fill(T::from(&sample));
+                }
+            },
+            |err| eprintln!("Audio stream error: {}", err),
+        )?;
+
+        Ok(stream)
+    }
+}
+
+pub struct Audio {
+    beeper: Beeper,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Audio {
+    // Sink picked from --wav / --mute, `device` opens the sound card otherwise
+    pub fn from_args(args: &ArgMatches, device: bool) -> Result<Option<Self>> {
+        let config = BeeperConfig {
+            waveform: match args.get_one::<String>("waveform").map(String::as_str) {
+                Some("sine") => Waveform::Sine,
+                _ => Waveform::Square,
+            },
+            frequency: *args
+                .get_one::<f32>("frequency")
+                .expect("Frequency should have default value"),
+            volume: args
+                .get_one::<f32>("volume")
+                .expect("Volume should have default value")
+                .clamp(0.0, 1.0),
+        };
+
+        let sink: Box<dyn AudioSink> = if let Some(path) = args.get_one::<PathBuf>("wav") {
+            Box::new(
+                WavSink::create(path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?,
+            )
+        } else if *args.get_one::<bool>("mute").unwrap_or(&false) || !device {
+            return Ok(None);
+        } else {
+            open_device()
+        };
+
+        Ok(Some(Self {
+            beeper: Beeper::new(config),
+            sink,
+        }))
+    }
+
+    pub fn frame(&mut self, emulator: &ChipEmulator) {
+        self.beeper.frame(emulator, self.sink.as_mut());
+    }
+}
+
+#[cfg(feature = "audio")]
+fn open_device() -> Box<dyn AudioSink> {
+    match DeviceSink::open() {
+        Ok(sink) => Box::new(sink),
+        Err(err) => {
+            eprintln!("Audio disabled: {}", err);
+            Box::new(NullSink)
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+fn open_device() -> Box<dyn AudioSink> {
+    Box::new(NullSink)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use rust8::{emulator::ChipConfig, platform::ChipPlatform, timers::TimerMode};
+
+    use super::*;
+
+    const GOLDEN_PATTERN: &[u8] = include_bytes!("../resources/test/xo_chip_pattern.wav");
+
+    // Loads the 16 byte pattern at 0x20E, sets the pitch to V0 and the sound timer to 6
+    fn pattern_rom(pattern: [u8; 16]) -> Vec<u8> {
+        let mut rom = vec![
+            0xA2, 0x0E, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A, 0x61, 0x06, 0xF1, 0x18, 0x12, 0x0C,
+        ];
+        rom.extend(pattern);
+        rom
+    }
+
+    fn xo_chip(rom: &[u8]) -> ChipEmulator {
+        let mut emulator = ChipEmulator::with_config(ChipConfig {
+            timing: TimerMode::Manual,
+            ..ChipPlatform::XoChip.config()
+        });
+        emulator.load_rom(rom).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn pattern_matches_golden_wav() {
+        let path = std::env::temp_dir().join(format!("rust8-pattern-{}.wav", process::id()));
+        let mut emulator = xo_chip(&pattern_rom([
+            0xFF, 0x00, 0xF0, 0x0F, 0xAA, 0x55, 0xCC, 0x33, 0x00, 0xFF, 0x0F, 0xF0, 0x55, 0xAA,
+            0x33, 0xCC,
+        ]));
+        let mut beeper = Beeper::new(BeeperConfig::default());
+
+        let mut sink = WavSink::create(&path).unwrap();
+        for _ in 0..8 {
+            emulator.run_frame(20).unwrap();
+            beeper.frame(&emulator, &mut sink);
+        }
+        drop(sink);
+
+        let wav = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(
+            wav == GOLDEN_PATTERN,
+            "Rendered WAV differs from the golden file"
+        );
+    }
+
+    #[test]
+    fn silent_pattern_replaces_the_beep() {
+        let mut emulator = xo_chip(&pattern_rom([0; 16]));
+        let mut beeper = Beeper::new(BeeperConfig::default());
+        emulator.run_frame(20).unwrap();
+
+        let samples = beeper.render(&emulator, SAMPLE_RATE);
+        assert!(emulator.timers().sound > 0);
+        assert!(samples.iter().all(|&sample| sample == samples[0]));
+    }
+}
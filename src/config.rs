@@ -0,0 +1,495 @@
+use std::{collections::HashMap, fmt, fs, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    emulator::{ChipConfig, LoadStoreQuirk},
+    keypad::ChipKey,
+    platform::ChipPlatform,
+    rom::rom_hash_hex,
+    timers::TimerMode,
+};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    palette::{parse_palette, Palette},
+    session::Hotkey,
+};
+
+// Keypad keys in the order of --keys, row by row
+pub const KEYPAD_LAYOUT: [ChipKey; 16] = [
+    ChipKey::Key1,
+    ChipKey::Key2,
+    ChipKey::Key3,
+    ChipKey::KeyC,
+    ChipKey::Key4,
+    ChipKey::Key5,
+    ChipKey::Key6,
+    ChipKey::KeyD,
+    ChipKey::Key7,
+    ChipKey::Key8,
+    ChipKey::Key9,
+    ChipKey::KeyE,
+    ChipKey::KeyA,
+    ChipKey::Key0,
+    ChipKey::KeyB,
+    ChipKey::KeyF,
+];
+const DEFAULT_KEYS: &str = "1234qwerasdfzxcv";
+const DEFAULT_HOTKEYS: [(Hotkey, Key); 9] = [
+    (Hotkey::Pause, Key::Space),
+    (Hotkey::Reset, Key::F(2)),
+    (Hotkey::Quit, Key::Escape),
+    (Hotkey::SaveState, Key::F(5)),
+    (Hotkey::LoadState, Key::F(9)),
+    (Hotkey::PreviousSlot, Key::F(6)),
+    (Hotkey::NextSlot, Key::F(7)),
+    (Hotkey::Rewind, Key::Backspace),
+    (Hotkey::SpeedUp, Key::Tab),
+];
+// Punctuation keys that can be bound besides letters and digits
+const PUNCTUATION: &str = "-=[]\\;',./`";
+
+// Keyboard key, independent of the frontend's key codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    // Lowercase letter, digit or punctuation
+    Char(char),
+    Space,
+    Escape,
+    Backspace,
+    Tab,
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+    F(u8),
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() || PUNCTUATION.contains(c) {
+                return Ok(Self::Char(c));
+            }
+        }
+
+        Ok(match name.as_str() {
+            "space" => Self::Space,
+            "escape" | "esc" => Self::Escape,
+            "backspace" => Self::Backspace,
+            "tab" => Self::Tab,
+            "enter" | "return" => Self::Enter,
+            "up" => Self::Up,
+            "down" => Self::Down,
+            "left" => Self::Left,
+            "right" => Self::Right,
+            _ => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => Self::F(n),
+                _ => return Err(format!("Unknown key '{}'", s)),
+            },
+        })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            Self::F(n) => write!(f, "F{}", n),
+            key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+// Keys bound to the keypad and to the hotkeys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bindings {
+    pub keypad: Vec<(ChipKey, Key)>,
+    pub hotkeys: Vec<(Hotkey, Key)>,
+}
+
+impl Bindings {
+    pub fn hotkey(&self, hotkey: Hotkey) -> Option<Key> {
+        self.hotkeys
+            .iter()
+            .find(|(bound, _)| *bound == hotkey)
+            .map(|(_, key)| *key)
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keypad: KEYPAD_LAYOUT
+                .into_iter()
+                .zip(DEFAULT_KEYS.chars().map(Key::Char))
+                .collect(),
+            hotkeys: DEFAULT_HOTKEYS.to_vec(),
+        }
+    }
+}
+
+// Defaults from the config file and the ROM database, command line options take precedence
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub clock_speed: Option<u32>,
+    pub ipf: Option<u32>,
+    pub platform: Option<ChipPlatform>,
+    pub modified_shift: Option<bool>,
+    pub load_store: Option<LoadStoreQuirk>,
+    pub vf_reset: Option<bool>,
+    pub modified_jump: Option<bool>,
+    pub clip_sprites: Option<bool>,
+    pub display_wait: Option<bool>,
+    pub palette: Option<Palette>,
+    pub phosphor_decay: Option<f32>,
+    // What the ROM uses keypad keys for, e.g. ("left", Key4)
+    pub key_hints: Vec<(String, ChipKey)>,
+    pub bindings: Bindings,
+}
+
+impl Settings {
+    // Fills unset values from `fallback`, the bindings are kept
+    pub fn or(self, fallback: Settings) -> Settings {
+        Settings {
+            clock_speed: self.clock_speed.or(fallback.clock_speed),
+            ipf: self.ipf.or(fallback.ipf),
+            platform: self.platform.or(fallback.platform),
+            modified_shift: self.modified_shift.or(fallback.modified_shift),
+            load_store: self.load_store.or(fallback.load_store),
+            vf_reset: self.vf_reset.or(fallback.vf_reset),
+            modified_jump: self.modified_jump.or(fallback.modified_jump),
+            clip_sprites: self.clip_sprites.or(fallback.clip_sprites),
+            display_wait: self.display_wait.or(fallback.display_wait),
+            palette: self.palette.or(fallback.palette),
+            phosphor_decay: self.phosphor_decay.or(fallback.phosphor_decay),
+            key_hints: if self.key_hints.is_empty() {
+                fallback.key_hints
+            } else {
+                self.key_hints
+            },
+            bindings: self.bindings,
+        }
+    }
+
+    // Quirks and timing on top of the platform preset
+    pub fn apply(&self, config: &mut ChipConfig) {
+        if let Some(ipf) = self.ipf {
+            config.timing = TimerMode::Cycles(ipf.max(1));
+        }
+        if let Some(modified_shift) = self.modified_shift {
+            config.modified_shift = modified_shift;
+        }
+        if let Some(load_store) = self.load_store {
+            config.load_store = load_store;
+        }
+        if let Some(vf_reset) = self.vf_reset {
+            config.vf_reset = vf_reset;
+        }
+        if let Some(modified_jump) = self.modified_jump {
+            config.modified_jump = modified_jump;
+        }
+        if let Some(clip_sprites) = self.clip_sprites {
+            config.clip_sprites = clip_sprites;
+        }
+        if let Some(display_wait) = self.display_wait {
+            config.display_wait = display_wait;
+        }
+    }
+
+    // Same settings without the platform and its quirks
+    fn without_quirks(self) -> Settings {
+        Settings {
+            platform: None,
+            modified_shift: None,
+            load_store: None,
+            vf_reset: None,
+            modified_jump: None,
+            clip_sprites: None,
+            display_wait: None,
+            ..self
+        }
+    }
+
+    pub fn chip_config(&self) -> ChipConfig {
+        let mut config = self
+            .platform
+            .map(|platform| platform.config())
+            .unwrap_or_default();
+        self.apply(&mut config);
+        config
+    }
+}
+
+// Table of the config file, ROM overrides use the same fields
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsFile {
+    clock_speed: Option<u32>,
+    ipf: Option<u32>,
+    platform: Option<String>,
+    modified_shift: Option<bool>,
+    modified_load: Option<bool>,
+    vf_reset: Option<bool>,
+    modified_jump: Option<bool>,
+    clip_sprites: Option<bool>,
+    display_wait: Option<bool>,
+    // Built-in palette name or colours, as for --palette
+    palette: Option<String>,
+    phosphor_decay: Option<f32>,
+    // Keypad key (hex digit) to key name
+    keys: HashMap<String, String>,
+    // Hotkey name to key name
+    hotkeys: HashMap<String, String>,
+    // Overrides keyed by the SHA-1 of the ROM
+    rom: HashMap<String, SettingsFile>,
+}
+
+impl SettingsFile {
+    fn merge(&self, other: &SettingsFile) -> SettingsFile {
+        let mut keys = self.keys.clone();
+        keys.extend(other.keys.clone());
+        let mut hotkeys = self.hotkeys.clone();
+        hotkeys.extend(other.hotkeys.clone());
+
+        SettingsFile {
+            clock_speed: other.clock_speed.or(self.clock_speed),
+            ipf: other.ipf.or(self.ipf),
+            platform: other.platform.clone().or_else(|| self.platform.clone()),
+            modified_shift: other.modified_shift.or(self.modified_shift),
+            modified_load: other.modified_load.or(self.modified_load),
+            vf_reset: other.vf_reset.or(self.vf_reset),
+            modified_jump: other.modified_jump.or(self.modified_jump),
+            clip_sprites: other.clip_sprites.or(self.clip_sprites),
+            display_wait: other.display_wait.or(self.display_wait),
+            palette: other.palette.clone().or_else(|| self.palette.clone()),
+            phosphor_decay: other.phosphor_decay.or(self.phosphor_decay),
+            keys,
+            hotkeys,
+            rom: HashMap::new(),
+        }
+    }
+
+    fn settings(&self) -> Result<Settings> {
+        let mut bindings = Bindings::default();
+
+        for (name, key) in &self.keys {
+            let chip_key = u8::from_str_radix(name, 16)
+                .ok()
+                .and_then(|key| ChipKey::try_from(key).ok())
+                .ok_or_else(|| anyhow!("Unknown keypad key '{}'", name))?;
+            let key = key.parse().map_err(|err: String| anyhow!(err))?;
+            for binding in bindings.keypad.iter_mut() {
+                if binding.0 == chip_key {
+                    binding.1 = key;
+                }
+            }
+        }
+        for (name, key) in &self.hotkeys {
+            let hotkey = Hotkey::ALL
+                .into_iter()
+                .find(|hotkey| hotkey.name() == name)
+                .ok_or_else(|| anyhow!("Unknown hotkey '{}'", name))?;
+            let key = key.parse().map_err(|err: String| anyhow!(err))?;
+            for binding in bindings.hotkeys.iter_mut() {
+                if binding.0 == hotkey {
+                    binding.1 = key;
+                }
+            }
+        }
+
+        Ok(Settings {
+            clock_speed: self.clock_speed,
+            ipf: self.ipf,
+            platform: self
+                .platform
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|err: String| anyhow!(err))?,
+            modified_shift: self.modified_shift,
+            load_store: self.modified_load.map(|modified_load| {
+                if modified_load {
+                    LoadStoreQuirk::Unchanged
+                } else {
+                    LoadStoreQuirk::IncrementXPlusOne
+                }
+            }),
+            vf_reset: self.vf_reset,
+            modified_jump: self.modified_jump,
+            clip_sprites: self.clip_sprites,
+            display_wait: self.display_wait,
+            palette: self
+                .palette
+                .as_deref()
+                .map(parse_palette)
+                .transpose()
+                .map_err(|err| anyhow!(err))?,
+            phosphor_decay: self.phosphor_decay,
+            key_hints: Vec::new(),
+            bindings,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    file: SettingsFile,
+}
+
+impl Config {
+    // Reads --config, or config.toml in the rust8 config directory if it exists
+    pub fn load(args: &ArgMatches) -> Result<Self> {
+        let path = match args.get_one::<PathBuf>("config") {
+            Some(path) => path.clone(),
+            None => match dirs::config_dir().map(|dir| dir.join("rust8").join("config.toml")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let mut file: SettingsFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config {}", path.display()))?;
+        file.rom = file
+            .rom
+            .into_iter()
+            .map(|(hash, settings)| (hash.to_ascii_lowercase(), settings))
+            .collect();
+
+        // Report mistakes on startup instead of when the ROM is loaded
+        let config = Self { file };
+        config
+            .file
+            .settings()
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        for (hash, settings) in &config.file.rom {
+            if !settings.rom.is_empty() {
+                bail!("ROM overrides for {} can't contain ROM overrides", hash);
+            }
+            config
+                .file
+                .merge(settings)
+                .settings()
+                .with_context(|| format!("Invalid ROM override {} in {}", hash, path.display()))?;
+        }
+
+        Ok(config)
+    }
+
+    // Settings for `rom`, its overrides come first, then the database entry and the defaults.
+    // The quirks of the defaults only apply when neither picked a platform for the ROM.
+    pub fn settings(&self, rom: Option<&[u8]>, database: &Database) -> Result<Settings> {
+        let overrides = rom.and_then(|rom| self.file.rom.get(&rom_hash_hex(rom)));
+        let mut settings = match overrides {
+            Some(overrides) => overrides.settings()?,
+            None => Settings::default(),
+        };
+
+        if let Some(entry) = rom.and_then(|rom| database.lookup(rom)) {
+            settings = settings.or(entry.settings());
+        }
+        let defaults = self.file.settings()?;
+        settings = if settings.platform.is_some() {
+            settings.or(defaults.without_quirks())
+        } else {
+            settings.or(defaults)
+        };
+
+        // Key bindings of the overrides only replace the keys they list
+        settings.bindings = match overrides {
+            Some(overrides) => self.file.merge(overrides).settings()?.bindings,
+            None => self.file.settings()?.bindings,
+        };
+
+        Ok(settings)
+    }
+}
+
+// Settings for `rom` from --config and --database, for the modes without a session
+pub fn rom_settings(args: &ArgMatches, rom: &[u8]) -> Result<Settings> {
+    let config = Config::load(args)?;
+    let database = Database::from_args(args)?;
+    config.settings(Some(rom), &database)
+}
+
+// Keypad bindings from --keys, 16 characters in KEYPAD_LAYOUT order
+pub fn parse_keys(keys: &str) -> Result<Vec<(ChipKey, Key)>> {
+    let keys = keys
+        .chars()
+        .map(|c| c.to_string().parse())
+        .collect::<Result<Vec<Key>, _>>()
+        .map_err(|err| anyhow!(err))?;
+    if keys.len() != KEYPAD_LAYOUT.len() {
+        bail!(
+            "Key mapping needs 16 characters for 123C 456D 789E A0BF, got {}",
+            keys.len()
+        );
+    }
+
+    Ok(KEYPAD_LAYOUT.into_iter().zip(keys).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 2] = [0x00, 0xE0];
+    const OTHER_ROM: [u8; 2] = [0x00, 0xEE];
+
+    fn database() -> Database {
+        let json = format!(
+            r#"[{{"title": "Test", "roms": {{"{}": {{"platforms": ["superchip"]}}}}}}]"#,
+            rom_hash_hex(&ROM)
+        );
+        Database::parse(&json).unwrap()
+    }
+
+    fn config(toml: &str) -> Config {
+        Config {
+            file: toml::from_str(toml).unwrap(),
+        }
+    }
+
+    #[test]
+    fn global_quirks_stay_off_detected_platforms() {
+        let superchip = ChipPlatform::SuperChip11.config();
+        assert!(superchip.modified_shift);
+        let config = config("modified_shift = false\nclock_speed = 900");
+        let database = database();
+
+        let settings = config.settings(Some(&ROM), &database).unwrap();
+        assert_eq!(settings.platform, Some(ChipPlatform::SuperChip11));
+        assert!(settings.chip_config().modified_shift);
+        assert_eq!(settings.clock_speed, Some(900));
+
+        let settings = config.settings(Some(&OTHER_ROM), &database).unwrap();
+        assert_eq!(settings.platform, None);
+        assert!(!settings.chip_config().modified_shift);
+    }
+
+    #[test]
+    fn rom_override_quirks_apply_to_detected_platforms() {
+        let toml = format!(
+            "modified_jump = false\n[rom.{}]\nmodified_shift = false",
+            rom_hash_hex(&ROM)
+        );
+        let config = config(&toml);
+
+        let settings = config.settings(Some(&ROM), &database()).unwrap();
+        let chip_config = settings.chip_config();
+        assert!(!chip_config.modified_shift);
+        assert_eq!(
+            chip_config.modified_jump,
+            ChipPlatform::SuperChip11.config().modified_jump
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::{emulator::LoadStoreQuirk, keypad::ChipKey, platform::ChipPlatform, rom::rom_hash_hex};
+use serde::Deserialize;
+
+use crate::{
+    config::Settings,
+    palette::{parse_color, Palette, PALETTE},
+};
+
+// programs.json in the format of the community chip-8-database
+const BUNDLED: &str = include_str!("../resources/database/programs.json");
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Program {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub release: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    // Keyed by the SHA-1 of the ROM
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RomEntry {
+    pub file: Option<String>,
+    // Platform ids, the preferred one first
+    pub platforms: Vec<String>,
+    // Instructions per frame
+    pub tickrate: Option<u32>,
+    pub colors: Option<Colors>,
+    // Action (e.g. "left") to keypad key
+    pub keys: HashMap<String, u8>,
+    // Quirks that differ from the platform's defaults
+    pub quirky_platforms: HashMap<String, Quirks>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub pixels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Quirks {
+    pub shift: Option<bool>,
+    pub memory_increment_by_x: Option<bool>,
+    pub memory_leave_i_unchanged: Option<bool>,
+    pub wrap: Option<bool>,
+    pub jump: Option<bool>,
+    pub vblank: Option<bool>,
+    pub logic: Option<bool>,
+}
+
+// Database entry of a single ROM
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    pub program: &'a Program,
+    pub rom: &'a RomEntry,
+}
+
+impl Entry<'_> {
+    // First listed platform the emulator supports and its database id
+    pub fn platform(&self) -> Option<(&str, ChipPlatform)> {
+        self.rom
+            .platforms
+            .iter()
+            .find_map(|id| Some((id.as_str(), platform(id)?)))
+    }
+
+    pub fn palette(&self) -> Option<Palette> {
+        let colors = self.rom.colors.as_ref()?;
+        if colors.pixels.is_empty() {
+            return None;
+        }
+
+        let mut palette = PALETTE;
+        for (color, text) in palette.iter_mut().zip(&colors.pixels) {
+            *color = parse_color(text)?;
+        }
+        Some(palette)
+    }
+
+    // Uses of the keypad keys, sorted by key
+    pub fn key_hints(&self) -> Vec<(String, ChipKey)> {
+        let mut hints: Vec<_> = self
+            .rom
+            .keys
+            .iter()
+            .filter_map(|(action, key)| Some((action.clone(), ChipKey::try_from(*key).ok()?)))
+            .collect();
+        hints.sort_by_key(|(action, key)| (*key as u8, action.clone()));
+        hints
+    }
+
+    pub fn settings(&self) -> Settings {
+        let mut settings = Settings {
+            clock_speed: self.rom.tickrate.map(|tickrate| tickrate.max(1) * 60),
+            palette: self.palette(),
+            key_hints: self.key_hints(),
+            ..Settings::default()
+        };
+
+        let (id, platform) = match self.platform() {
+            Some(platform) => platform,
+            None => return settings,
+        };
+        settings.platform = Some(platform);
+        // Modern interpreters run CHIP-8 programs without the VIP's logic and display quirks
+        if id == "modernChip8" {
+            settings.vf_reset = Some(false);
+            settings.display_wait = Some(false);
+        }
+
+        if let Some(quirks) = self.rom.quirky_platforms.get(id) {
+            settings.modified_shift = quirks.shift;
+            // A quirk set to false only means that one doesn't apply, the platform decides the rest
+            settings.load_store = match (
+                quirks.memory_leave_i_unchanged,
+                quirks.memory_increment_by_x,
+            ) {
+                (Some(true), _) => Some(LoadStoreQuirk::Unchanged),
+                (_, Some(true)) => Some(LoadStoreQuirk::IncrementX),
+                _ => None,
+            };
+            settings.clip_sprites = quirks.wrap.map(|wrap| !wrap);
+            settings.modified_jump = quirks.jump;
+            settings.display_wait = quirks.vblank.or(settings.display_wait);
+            settings.vf_reset = quirks.logic.or(settings.vf_reset);
+        }
+
+        settings
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    programs: Vec<Program>,
+    // ROM hash to the index of its program
+    index: HashMap<String, usize>,
+}
+
+impl Database {
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("Bundled ROM database should be valid")
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let programs: Vec<Program> = serde_json::from_str(text)?;
+        let index = programs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, program)| {
+                program
+                    .roms
+                    .keys()
+                    .map(move |hash| (hash.to_ascii_lowercase(), i))
+            })
+            .collect();
+
+        Ok(Self { programs, index })
+    }
+
+    // Reads --database, or the bundled database
+    pub fn from_args(args: &ArgMatches) -> Result<Self> {
+        match args.get_one::<PathBuf>("database") {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read database {}", path.display()))?;
+                Self::parse(&text)
+                    .with_context(|| format!("Failed to parse database {}", path.display()))
+            }
+            None => Ok(Self::bundled()),
+        }
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<Entry<'_>> {
+        let hash = rom_hash_hex(rom);
+        let program = &self.programs[*self.index.get(&hash)?];
+        let rom = program
+            .roms
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&hash))
+            .map(|(_, rom)| rom)?;
+
+        Some(Entry { program, rom })
+    }
+}
+
+// Platform ids of the database, CHIP-8 variants the emulator has no preset for are skipped
+fn platform(id: &str) -> Option<ChipPlatform> {
+    Some(match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => ChipPlatform::CosmacVip,
+        "chip48" => ChipPlatform::Chip48,
+        "superchip1" => ChipPlatform::SuperChip10,
+        "superchip" => ChipPlatform::SuperChip11,
+        "xochip" => ChipPlatform::XoChip,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 2] = [0x00, 0xE0];
+
+    fn settings(quirks: &str) -> Settings {
+        let json = format!(
+            r#"[{{"title": "Test", "roms": {{"{}": {{
+                "platforms": ["chip48"],
+                "quirkyPlatforms": {{"chip48": {}}}
+            }}}}}}]"#,
+            rom_hash_hex(&ROM),
+            quirks
+        );
+        let database = Database::parse(&json).unwrap();
+        database.lookup(&ROM).unwrap().settings()
+    }
+
+    #[test]
+    fn load_store_only_follows_true_quirks() {
+        let load_store = |quirks| settings(quirks).load_store;
+
+        assert_eq!(load_store("{}"), None);
+        assert_eq!(load_store(r#"{"memoryLeaveIUnchanged": false}"#), None);
+        assert_eq!(
+            load_store(r#"{"memoryLeaveIUnchanged": false, "memoryIncrementByX": false}"#),
+            None
+        );
+        assert_eq!(
+            load_store(r#"{"memoryLeaveIUnchanged": true}"#),
+            Some(LoadStoreQuirk::Unchanged)
+        );
+        assert_eq!(
+            load_store(r#"{"memoryLeaveIUnchanged": false, "memoryIncrementByX": true}"#),
+            Some(LoadStoreQuirk::IncrementX)
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    debugger::{Breakpoint, Comparison, Condition, DebugRegister, StopReason, Watchpoint},
+    disasm::{self, Syntax},
+    emulator::{ChipEmulator, StepOutcome},
+    error::ChipError,
+    keypad::ChipKey,
+    memory::AccessKind,
+    timers::TimerMode,
+};
+
+use crate::{args, config, headless::display_ascii, trace};
+
+// Upper bound for continue / next / until, so an endless loop doesn't hang the prompt
+const MAX_CYCLES: u64 = 10_000_000;
+// Instructions listed around PC
+const CONTEXT_BEFORE: u16 = 3;
+const CONTEXT_AFTER: u16 = 6;
+
+const HELP: &str = "\
+step [COUNT]             s   Execute COUNT instructions (default 1)
+next                     n   Step over CALL
+continue                 c   Run until a breakpoint is hit
+until ADDR               u   Run until PC reaches ADDR
+break ADDR [if COND]     b   Set a breakpoint, e.g. 'b 0x2A0 if v3 == 5'
+delete [ADDR]            d   Remove the breakpoint at ADDR, or all breakpoints
+watch ADDR[-END] [rwx]   w   Stop on reads / writes / execution in a range (default w)
+unwatch [ADDR]               Remove watchpoints starting at ADDR, or all watchpoints
+breakpoints              bl  List breakpoints and watchpoints
+regs                     r   Show registers, stack and timers
+list [ADDR]              l   Disassemble around ADDR (default PC)
+screen                       Show the display
+key +K / key -K              Press / release key K (hex)
+help                     h   Show this help
+quit                     q   Exit the debugger
+An empty line repeats the last command.";
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let rom = args.get_one::<PathBuf>("rom").expect("ROM is required");
+    let mut emulator = load_emulator(args, rom)?;
+
+    println!("Rust8 debugger, type 'help' for a list of commands");
+    print_location(&emulator);
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("(rust8) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let line = line.trim();
+        if !line.is_empty() {
+            last_command = line.to_string();
+        }
+        if last_command.is_empty() {
+            continue;
+        }
+
+        match execute(&mut emulator, &last_command) {
+            Ok(true) => break,
+            Ok(false) => (),
+            Err(err) => println!("Error: {}", err),
+        }
+    }
+
+    Ok(())
+}
+
+// Emulator for interactive debugging, timers follow executed instructions
+// since they'd otherwise run down while execution is stopped
+pub fn load_emulator(args: &ArgMatches, rom: &Path) -> Result<ChipEmulator> {
+    let rom = fs::read(rom).with_context(|| format!("Failed to open {}", rom.display()))?;
+    let settings = config::rom_settings(args, &rom)?;
+
+    let mut config = args::configured_chip_config(args, &settings);
+    if config.timing == TimerMode::WallClock {
+        let clock_speed = args::clock_speed(args, &settings);
+        config.timing = TimerMode::Cycles((clock_speed / 60).max(1));
+    }
+
+    let mut emulator = ChipEmulator::with_config(config);
+    emulator.load_rom(rom.as_slice())?;
+    trace::attach(args, &mut emulator)?;
+
+    Ok(emulator)
+}
+
+// Returns true when the debugger should exit
+fn execute(emulator: &mut ChipEmulator, line: &str) -> Result<bool> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let (command, operands) = words.split_first().expect("Line is not empty");
+
+    match *command {
+        "s" | "step" => {
+            let count = operands.first().map(|n| parse_number(n)).transpose()?;
+            for _ in 0..count.unwrap_or(1) {
+                match emulator.step() {
+                    Ok(StepOutcome::WaitingForKey) => {
+                        report(Ok(StopReason::WaitingForKey));
+                        break;
+                    }
+                    Ok(StepOutcome::Exited) => {
+                        report(Ok(StopReason::Exited));
+                        break;
+                    }
+                    Ok(_) => (),
+                    Err(err) => {
+                        report(Err(err));
+                        break;
+                    }
+                }
+            }
+            print_location(emulator);
+        }
+        "n" | "next" => {
+            report(emulator.step_over(MAX_CYCLES));
+            print_location(emulator);
+        }
+        "c" | "continue" => {
+            report(emulator.resume(MAX_CYCLES));
+            print_location(emulator);
+        }
+        "u" | "until" => {
+            let address = parse_address(operands.first())?;
+            report(emulator.run_to(address, MAX_CYCLES));
+            print_location(emulator);
+        }
+        "b" | "break" => {
+            let address = parse_address(operands.first())?;
+            let condition = match &operands[1..] {
+                [] => None,
+                ["if", register, comparison, value] => Some(Condition {
+                    register: parse_register(register)?,
+                    comparison: parse_comparison(comparison)?,
+                    value: parse_number(value)? as u16,
+                }),
+                _ => bail!("Expected 'break ADDR [if REG OP VALUE]'"),
+            };
+            emulator.add_breakpoint(Breakpoint { address, condition });
+            println!("Breakpoint set at 0x{:03X}", address);
+        }
+        "d" | "delete" => match operands.first() {
+            Some(address) => {
+                let address = parse_address(Some(address))?;
+                if !emulator.remove_breakpoint(address) {
+                    bail!("No breakpoint at 0x{:03X}", address);
+                }
+            }
+            None => emulator.clear_breakpoints(),
+        },
+        "w" | "watch" => {
+            let range = operands
+                .first()
+                .ok_or_else(|| anyhow!("Expected an address range"))?;
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (parse_address(Some(&start))?, parse_address(Some(&end))?),
+                None => {
+                    let address = parse_address(Some(range))?;
+                    (address, address)
+                }
+            };
+            if start > end {
+                bail!("Range start is past its end");
+            }
+
+            let flags = operands.get(1).copied().unwrap_or("w");
+            if flags.is_empty() || !flags.chars().all(|c| "rwx".contains(c)) {
+                bail!("Expected a combination of 'r', 'w' and 'x'");
+            }
+
+            let watchpoint = Watchpoint {
+                start,
+                end,
+                read: flags.contains('r'),
+                write: flags.contains('w'),
+                execute: flags.contains('x'),
+            };
+            emulator.add_watchpoint(watchpoint);
+            println!("Watchpoint set at {}", watchpoint);
+        }
+        "unwatch" => match operands.first() {
+            Some(address) => {
+                let address = parse_address(Some(address))?;
+                if !emulator.remove_watchpoint(address) {
+                    bail!("No watchpoint at 0x{:03X}", address);
+                }
+            }
+            None => emulator.clear_watchpoints(),
+        },
+        "bl" | "breakpoints" => {
+            if emulator.breakpoints().is_empty() && emulator.watchpoints().is_empty() {
+                println!("No breakpoints");
+            }
+            for watchpoint in emulator.watchpoints() {
+                println!("{} watch", watchpoint);
+            }
+            for breakpoint in emulator.breakpoints() {
+                match breakpoint.condition {
+                    Some(condition) => println!("0x{:03X} if {}", breakpoint.address, condition),
+                    None => println!("0x{:03X}", breakpoint.address),
+                }
+            }
+        }
+        "r" | "regs" => print_registers(emulator),
+        "l" | "list" => {
+            let address = match operands.first() {
+                Some(address) => parse_address(Some(address))?,
+                None => emulator.pc(),
+            };
+            print_disassembly(emulator, address);
+        }
+        "screen" => print!("{}", display_ascii(emulator)),
+        "key" => {
+            let (key, pressed) = parse_key_event(
+                operands
+                    .first()
+                    .ok_or_else(|| anyhow!("Expected '+K' or '-K'"))?,
+            )?;
+
+            if pressed {
+                emulator.press(key);
+            } else {
+                emulator.release(key);
+            }
+        }
+        "h" | "help" => println!("{}", HELP),
+        "q" | "quit" => return Ok(true),
+        _ => bail!(
+            "Unknown command '{}', type 'help' for a list of commands",
+            command
+        ),
+    }
+
+    Ok(false)
+}
+
+fn report(result: Result<StopReason, ChipError>) {
+    match result {
+        Ok(StopReason::Breakpoint(address)) => println!("Breakpoint hit at 0x{:03X}", address),
+        Ok(StopReason::Watchpoint(hit)) => {
+            let kind = match hit.access.kind {
+                AccessKind::Read => "Read from",
+                AccessKind::Write => "Write to",
+                AccessKind::Execute => "Execution of",
+            };
+            println!(
+                "Watchpoint {} hit: {} 0x{:03X}-0x{:03X} by '{}' at 0x{:03X}",
+                hit.watchpoint,
+                kind,
+                hit.access.address,
+                hit.access.address + hit.access.len - 1,
+                disasm::mnemonic(&hit.instruction, Syntax::Cowgod),
+                hit.pc
+            );
+        }
+        Ok(StopReason::WaitingForKey) => println!("Waiting for a key press, use 'key +K'"),
+        Ok(StopReason::Exited) => println!("Program exited"),
+        Ok(StopReason::CycleLimit) => println!("Stopped after {} cycles", MAX_CYCLES),
+        Ok(StopReason::Stepped | StopReason::Reached) => (),
+        Err(err) => println!("Emulation halted: {}", err),
+    }
+}
+
+fn print_location(emulator: &ChipEmulator) {
+    print_disassembly(emulator, emulator.pc());
+    print_registers(emulator);
+}
+
+fn print_disassembly(emulator: &ChipEmulator, address: u16) {
+    let memory = emulator.memory();
+    let start = address.saturating_sub(CONTEXT_BEFORE * 2);
+    let end = (address as usize + CONTEXT_AFTER as usize * 2).min(memory.len());
+    if start as usize >= end {
+        return;
+    }
+
+    for line in disasm::disassemble_linear(&memory[start as usize..end], start, emulator.config()) {
+        let marker = if line.address == emulator.pc() {
+            "=>"
+        } else if emulator
+            .breakpoints()
+            .iter()
+            .any(|bp| bp.address == line.address)
+        {
+            " *"
+        } else {
+            "  "
+        };
+        let raw = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>();
+
+        println!(
+            "{} {:04X}  {:<8}  {}",
+            marker,
+            line.address,
+            raw,
+            line.mnemonic(Syntax::Cowgod)
+        );
+    }
+}
+
+fn print_registers(emulator: &ChipEmulator) {
+    let timers = emulator.timers();
+    println!(
+        "PC {:04X}  I {:04X}  SP {:X}  DT {:02X}  ST {:02X}",
+        emulator.pc(),
+        emulator.i(),
+        emulator.sp(),
+        timers.delay,
+        timers.sound
+    );
+
+    for (row, values) in emulator.registers().as_slice().chunks(8).enumerate() {
+        let registers = values
+            .iter()
+            .enumerate()
+            .map(|(x, value)| format!("V{:X} {:02X}", row * 8 + x, value))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", registers);
+    }
+
+    let stack = emulator
+        .stack()
+        .iter()
+        .map(|address| format!("{:04X}", address))
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!("Stack [{}]", stack);
+}
+
+// `+K` presses and `-K` releases the hex key K
+pub fn parse_key_event(text: &str) -> Result<(ChipKey, bool)> {
+    let (pressed, value) = if let Some(value) = text.strip_prefix('+') {
+        (true, value)
+    } else if let Some(value) = text.strip_prefix('-') {
+        (false, value)
+    } else {
+        bail!("Expected '+K' or '-K'");
+    };
+    let key = u8::from_str_radix(value, 16)
+        .ok()
+        .and_then(|key| ChipKey::try_from(key).ok())
+        .ok_or_else(|| anyhow!("Invalid key '{}'", value))?;
+
+    Ok((key, pressed))
+}
+
+fn parse_number(text: &str) -> Result<u64> {
+    let parsed = match text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.map_err(|_| anyhow!("Invalid number '{}'", text))
+}
+
+fn parse_address(text: Option<&&str>) -> Result<u16> {
+    let text = text.ok_or_else(|| anyhow!("Expected an address"))?;
+    u16::try_from(parse_number(text)?).map_err(|_| anyhow!("Address '{}' out of range", text))
+}
+
+fn parse_register(text: &str) -> Result<DebugRegister> {
+    let upper = text.to_ascii_uppercase();
+
+    Ok(match upper.as_str() {
+        "I" => DebugRegister::I,
+        "DT" => DebugRegister::Delay,
+        "ST" => DebugRegister::Sound,
+        _ => upper
+            .strip_prefix('V')
+            .filter(|x| x.len() == 1)
+            .and_then(|x| u8::from_str_radix(x, 16).ok())
+            .map(DebugRegister::V)
+            .ok_or_else(|| anyhow!("Unknown register '{}'", text))?,
+    })
+}
+
+fn parse_comparison(text: &str) -> Result<Comparison> {
+    Ok(match text {
+        "==" => Comparison::Eq,
+        "!=" => Comparison::Ne,
+        "<" => Comparison::Lt,
+        "<=" => Comparison::Le,
+        ">" => Comparison::Gt,
+        ">=" => Comparison::Ge,
+        _ => bail!("Unknown comparison '{}'", text),
+    })
+}
@@ -0,0 +1,66 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    disasm::{self, Syntax},
+    memory::PROGRAM_SPACE_START,
+};
+
+use crate::args;
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let config = args::variant_config(args);
+    let rom_path = args.get_one::<PathBuf>("rom").expect("ROM is required");
+    let rom =
+        fs::read(rom_path).with_context(|| format!("Failed to read {}", rom_path.display()))?;
+    let syntax = match args.get_one::<String>("syntax").map(String::as_str) {
+        Some("octo") => Syntax::Octo,
+        _ => Syntax::Cowgod,
+    };
+
+    let origin = PROGRAM_SPACE_START as u16;
+    let lines = if *args.get_one::<bool>("linear").unwrap_or(&false) {
+        disasm::disassemble_linear(&rom, origin, &config)
+    } else {
+        disasm::disassemble(&rom, origin, &config)
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for line in lines {
+        let raw = line
+            .bytes
+            .chunks(2)
+            .map(|word| word.iter().map(|byte| format!("{:02X}", byte)).collect())
+            .collect::<Vec<String>>()
+            .join(" ");
+        write!(
+            out,
+            "{:04X}  {:<9}  {}",
+            line.address,
+            raw,
+            line.mnemonic(syntax)
+        )?;
+
+        // Show data bytes as sprite rows
+        if line.instruction.is_none() {
+            let row = (0..8)
+                .map(|bit| {
+                    if line.bytes[0] & (0x80 >> bit) != 0 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            write!(out, "  ; {}", row)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
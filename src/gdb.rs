@@ -0,0 +1,584 @@
+use std::{
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    debugger::{Breakpoint, StopReason, Watchpoint},
+    emulator::{ChipEmulator, StepOutcome},
+    error::ChipError,
+    memory::AccessKind,
+};
+
+use crate::debugger;
+
+// Cycles executed between checks for an interrupt (Ctrl-C) from the client
+const RESUME_CHUNK: u64 = 10_000;
+const PACKET_SIZE: usize = 0x1000;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+// Register file: V0-VF, I, PC, SP, DT, ST. I and PC are 16 bit little-endian
+const REGISTER_SIZES: [usize; 21] = [
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1,
+];
+
+enum Response {
+    Reply(String),
+    ReplyAndQuit(String),
+    Quit,
+}
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let address = args
+        .get_one::<String>("gdb")
+        .expect("GDB address is required");
+    let rom = args
+        .get_one::<PathBuf>("rom")
+        .context("A ROM is required to start the GDB server")?;
+    let emulator = debugger::load_emulator(args, rom)?;
+
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+    eprintln!("Waiting for a GDB connection on {}", address);
+
+    let (stream, peer) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    eprintln!("GDB connected from {}", peer);
+
+    GdbServer {
+        stream,
+        emulator,
+        last_packet: Vec::new(),
+    }
+    .serve()
+}
+
+struct GdbServer {
+    stream: TcpStream,
+    emulator: ChipEmulator,
+    // Resent when the client asks for a retransmission
+    last_packet: Vec<u8>,
+}
+
+impl GdbServer {
+    fn serve(&mut self) -> Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Response::Reply(reply) => self.send(&reply)?,
+                Response::ReplyAndQuit(reply) => {
+                    self.send(&reply)?;
+                    break;
+                }
+                Response::Quit => break,
+            }
+        }
+
+        eprintln!("GDB disconnected");
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Result<Response> {
+        let reply = match packet {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "qAttached" => "1".into(),
+            "qC" => "QC1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            "vCont?" => "vCont;c;C;s;S".into(),
+            "g" => self.read_registers(),
+            "c" => self.resume()?,
+            "s" => self.step(),
+            "k" => return Ok(Response::Quit),
+            "D" => return Ok(Response::ReplyAndQuit("OK".into())),
+            _ if packet.starts_with("qSupported") => {
+                format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+            }
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                self.target_xml(&packet["qXfer:features:read:target.xml:".len()..])
+            }
+            _ if packet.starts_with("qRcmd,") => self.monitor(&packet["qRcmd,".len()..])?,
+            _ if packet.starts_with("vCont;c") || packet.starts_with("vCont;C") => self.resume()?,
+            _ if packet.starts_with("vCont;s") || packet.starts_with("vCont;S") => self.step(),
+            _ if packet.starts_with('H') => "OK".into(),
+            _ if packet.starts_with('G') => self.write_registers(&packet[1..]),
+            _ if packet.starts_with('p') => self.read_register(&packet[1..]),
+            _ if packet.starts_with('P') => self.write_register(&packet[1..]),
+            _ if packet.starts_with('m') => self.read_memory(&packet[1..]),
+            _ if packet.starts_with('M') => self.write_memory(&packet[1..]),
+            _ if packet.starts_with('Z') => self.breakpoint(&packet[1..], true),
+            _ if packet.starts_with('z') => self.breakpoint(&packet[1..], false),
+            // Empty reply for unsupported packets
+            _ => String::new(),
+        };
+
+        Ok(Response::Reply(reply))
+    }
+
+    fn resume(&mut self) -> io::Result<String> {
+        loop {
+            match self.emulator.resume(RESUME_CHUNK) {
+                Ok(StopReason::CycleLimit) => (),
+                // Keys can only change through monitor commands, which can't arrive while running
+                Ok(StopReason::WaitingForKey) => thread::sleep(Duration::from_millis(10)),
+                result => return Ok(stop_reply(result)),
+            }
+
+            if self.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    fn step(&mut self) -> String {
+        match self.emulator.step() {
+            Ok(StepOutcome::Exited) => stop_reply(Ok(StopReason::Exited)),
+            Ok(_) => stop_reply(Ok(StopReason::Stepped)),
+            Err(err) => stop_reply(Err(err)),
+        }
+    }
+
+    fn register(&self, regnum: usize) -> Option<Vec<u8>> {
+        let emu = &self.emulator;
+
+        Some(match regnum {
+            0..=15 => vec![emu.registers()[regnum as u8]],
+            16 => emu.i().to_le_bytes().to_vec(),
+            17 => emu.pc().to_le_bytes().to_vec(),
+            18 => vec![emu.sp()],
+            19 => vec![emu.timers().delay],
+            20 => vec![emu.timers().sound],
+            _ => return None,
+        })
+    }
+
+    fn set_register(&mut self, regnum: usize, bytes: &[u8]) -> bool {
+        let emu = &mut self.emulator;
+
+        match (regnum, bytes) {
+            (0..=15, [value]) => emu.registers_mut()[regnum as u8] = *value,
+            (16, [low, high]) => emu.set_i(u16::from_le_bytes([*low, *high])),
+            (17, [low, high]) => emu.set_pc(u16::from_le_bytes([*low, *high])),
+            // The stack pointer is read only
+            (18, [_]) => (),
+            (19, [value]) => emu.timers_mut().delay = *value,
+            (20, [value]) => emu.timers_mut().sound = *value,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_SIZES.len())
+            .filter_map(|regnum| self.register(regnum))
+            .map(|bytes| to_hex(&bytes))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let bytes = match from_hex(data) {
+            Some(bytes) if bytes.len() == REGISTER_SIZES.iter().sum::<usize>() => bytes,
+            _ => return "E01".into(),
+        };
+
+        let mut offset = 0;
+        for (regnum, size) in REGISTER_SIZES.into_iter().enumerate() {
+            self.set_register(regnum, &bytes[offset..offset + size]);
+            offset += size;
+        }
+
+        "OK".into()
+    }
+
+    fn read_register(&self, data: &str) -> String {
+        parse_hex(data)
+            .and_then(|regnum| self.register(regnum))
+            .map_or_else(|| "E01".into(), |bytes| to_hex(&bytes))
+    }
+
+    fn write_register(&mut self, data: &str) -> String {
+        let written = data.split_once('=').and_then(|(regnum, value)| {
+            let (regnum, value) = (parse_hex(regnum)?, from_hex(value)?);
+            self.set_register(regnum, &value).then_some(())
+        });
+
+        written.map_or_else(|| "E01".into(), |_| "OK".into())
+    }
+
+    // Reads by the client don't count as accesses for watchpoints
+    fn read_memory(&self, data: &str) -> String {
+        let memory = self.emulator.memory();
+        let range = parse_range(data).and_then(|(address, len)| {
+            let len = len.min(PACKET_SIZE / 2);
+            memory.get(address..address.checked_add(len)?)
+        });
+
+        range.map_or_else(|| "E01".into(), to_hex)
+    }
+
+    fn write_memory(&mut self, data: &str) -> String {
+        let written = data.split_once(':').and_then(|(range, bytes)| {
+            let (address, len) = parse_range(range)?;
+            let bytes = from_hex(bytes).filter(|bytes| bytes.len() == len)?;
+            self.emulator.write_memory(address, &bytes).ok()
+        });
+
+        written.map_or_else(|| "E01".into(), |_| "OK".into())
+    }
+
+    // Z0/Z1 are execution breakpoints, Z2/Z3/Z4 write/read/access watchpoints
+    fn breakpoint(&mut self, data: &str, insert: bool) -> String {
+        let mut fields = data.split(',');
+        let (kind, address, len) = match (
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) {
+            (Some(kind), Some(address), Some(len)) if address <= u16::MAX as usize => {
+                (kind, address as u16, len.max(1))
+            }
+            _ => return "E01".into(),
+        };
+
+        match (kind, insert) {
+            (0 | 1, true) => self.emulator.add_breakpoint(Breakpoint {
+                address,
+                condition: None,
+            }),
+            (0 | 1, false) => {
+                self.emulator.remove_breakpoint(address);
+            }
+            (2..=4, true) => {
+                // The watched range has to end inside the address space
+                let end = (address as usize)
+                    .checked_add(len - 1)
+                    .and_then(|end| u16::try_from(end).ok());
+                let end = match end {
+                    Some(end) => end,
+                    None => return "E01".into(),
+                };
+                self.emulator.add_watchpoint(Watchpoint {
+                    start: address,
+                    end,
+                    read: kind != 2,
+                    write: kind != 3,
+                    execute: false,
+                })
+            }
+            (2..=4, false) => {
+                self.emulator.remove_watchpoint(address);
+            }
+            _ => return String::new(),
+        }
+
+        "OK".into()
+    }
+
+    fn target_xml(&self, data: &str) -> String {
+        let xml = target_description();
+        let (offset, end) = match parse_range(data)
+            .and_then(|(offset, len)| Some((offset, offset.checked_add(len)?)))
+        {
+            Some(range) => range,
+            None => return "E01".into(),
+        };
+        let chunk = xml.get(offset..end.min(xml.len())).unwrap_or("");
+
+        if offset + chunk.len() < xml.len() {
+            format!("m{}", chunk)
+        } else {
+            format!("l{}", chunk)
+        }
+    }
+
+    // `monitor key +K` / `monitor key -K` presses and releases keypad keys
+    fn monitor(&mut self, data: &str) -> io::Result<String> {
+        let command = from_hex(data)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default();
+
+        let result = match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["key", event] => debugger::parse_key_event(event).map(|(key, pressed)| {
+                if pressed {
+                    self.emulator.press(key);
+                } else {
+                    self.emulator.release(key);
+                }
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Unknown monitor command, try 'key +K' or 'key -K'"
+            )),
+        };
+
+        if let Err(err) = result {
+            self.send(&format!("O{}", to_hex(format!("{}\n", err).as_bytes())))?;
+        }
+
+        Ok("OK".into())
+    }
+
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                Some(b'$') => (),
+                Some(b'-') => {
+                    let packet = self.last_packet.clone();
+                    self.stream.write_all(&packet)?;
+                    continue;
+                }
+                // Acks and interrupts while already stopped
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = match checksum {
+                [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                _ => return Ok(None),
+            };
+
+            if expected == Some(checksum_of(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.last_packet = packet.into_bytes();
+        self.stream.write_all(&self.last_packet)
+    }
+
+    // Polls for a Ctrl-C (0x03) without blocking, a closed connection also stops execution
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut byte = [0];
+
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Ok(true),
+            Ok(_) => Ok(byte[0] == 0x03),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn stop_reply(result: Result<StopReason, ChipError>) -> String {
+    match result {
+        Ok(StopReason::Exited) => "W00".into(),
+        Ok(StopReason::Watchpoint(hit)) => {
+            let kind = match hit.access.kind {
+                _ if hit.watchpoint.read && hit.watchpoint.write => "awatch",
+                AccessKind::Write => "watch",
+                AccessKind::Read => "rwatch",
+                AccessKind::Execute => return format!("S{:02x}", SIGTRAP),
+            };
+            let address = hit.access.address.max(hit.watchpoint.start as usize);
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, address)
+        }
+        Ok(_) => format!("S{:02x}", SIGTRAP),
+        Err(ChipError::InvalidOpcode { .. }) => format!("S{:02x}", SIGILL),
+        Err(_) => format!("S{:02x}", SIGSEGV),
+    }
+}
+
+fn target_description() -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\"?>\n",
+        "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n",
+        "<target version=\"1.0\">\n",
+        "<feature name=\"org.rust8.chip8\">\n",
+    ));
+    for x in 0..16 {
+        let _ = writeln!(xml, "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", x);
+    }
+    xml.push_str(concat!(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n",
+        "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n",
+        "<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\n",
+        "<reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\n",
+        "<reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\n",
+        "</feature>\n",
+        "</target>\n",
+    ));
+    xml
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// `addr,len` as used by m, M and qXfer packets
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::JoinHandle;
+
+    use rust8::emulator::ChipConfig;
+
+    use super::*;
+
+    // LD V0, 0x12 / ADD V0, 1 / JP 0x202
+    const ROM: [u8; 6] = [0x60, 0x12, 0x70, 0x01, 0x12, 0x02];
+
+    struct Client {
+        stream: TcpStream,
+        server: JoinHandle<Result<()>>,
+    }
+
+    impl Client {
+        // Serves a fresh emulator with ROM loaded on a loopback port
+        fn connect() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+
+            let server = thread::spawn(move || {
+                let mut emulator = ChipEmulator::with_config(ChipConfig::default());
+                emulator.load_rom(&ROM[..])?;
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+
+                GdbServer {
+                    stream,
+                    emulator,
+                    last_packet: Vec::new(),
+                }
+                .serve()
+            });
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+
+            Self { stream, server }
+        }
+
+        fn request(&mut self, packet: &str) -> String {
+            let framed = format!("${}#{:02x}", packet, checksum_of(packet.as_bytes()));
+            self.stream.write_all(framed.as_bytes()).unwrap();
+
+            let mut reply = Vec::new();
+            let mut byte = [0];
+            while reply.last() != Some(&b'#') {
+                self.stream.read_exact(&mut byte).unwrap();
+                reply.push(byte[0]);
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            self.stream.write_all(b"+").unwrap();
+
+            let reply = String::from_utf8(reply).unwrap();
+            let data = reply
+                .strip_prefix("+$")
+                .and_then(|reply| reply.strip_suffix('#'))
+                .unwrap_or_else(|| panic!("Malformed reply {:?}", reply));
+            assert_eq!(
+                std::str::from_utf8(&checksum).unwrap(),
+                format!("{:02x}", checksum_of(data.as_bytes()))
+            );
+            data.to_string()
+        }
+
+        fn detach(mut self) {
+            assert_eq!(self.request("D"), "OK");
+            self.server.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn registers_memory_and_execution() {
+        let mut client = Client::connect();
+
+        // V0-VF, I, PC, SP, DT, ST
+        let registers = format!("{}0000000200{}", "00".repeat(16), "0000");
+        assert_eq!(client.request("g"), registers);
+
+        assert_eq!(client.request("m200,6"), "601270011202");
+        assert_eq!(client.request("M300,3:abcdef"), "OK");
+        assert_eq!(client.request("m300,3"), "abcdef");
+
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p0"), "12");
+        assert_eq!(client.request("p11"), "0202");
+
+        assert_eq!(client.request("Z0,204,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p11"), "0402");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p0"), "14");
+
+        client.detach();
+    }
+
+    #[test]
+    fn out_of_range_requests_are_errors() {
+        let mut client = Client::connect();
+
+        assert_eq!(client.request("mffffffffffffffff,1"), "E01");
+        assert_eq!(client.request("m1000,1"), "E01");
+        assert_eq!(client.request("Mffffffffffffffff,1:00"), "E01");
+        assert_eq!(client.request("Z2,200,10000"), "E01");
+        assert_eq!(client.request("Z2,200,ffffffffffffffff"), "E01");
+        assert_eq!(client.request("Z2,ffff,2"), "E01");
+        assert_eq!(client.request("Z2,ffff,1"), "OK");
+        assert_eq!(
+            client.request("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+            "E01"
+        );
+
+        client.detach();
+    }
+}
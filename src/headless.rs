@@ -0,0 +1,334 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    emulator::{ChipEmulator, StepOutcome},
+    error::ChipError,
+    keypad::ChipKey,
+    movie::Movie,
+    timers::TimerMode,
+};
+use serde_json::json;
+
+use crate::{
+    args,
+    audio::Audio,
+    config, movie,
+    palette::{Palette, PALETTE},
+    trace,
+};
+
+// Frames executed when neither --cycles nor --frames is given
+const DEFAULT_FRAMES: u64 = 600;
+const ASCII_PIXELS: [char; 4] = [' ', '#', 'o', '@'];
+
+#[derive(Debug, Clone, Copy)]
+struct KeyEvent {
+    frame: u64,
+    key: ChipKey,
+    pressed: bool,
+}
+
+// Where the frame loop takes keypad input from
+enum MovieMode<'a> {
+    Off,
+    // Input script, recorded into the movie
+    Record(&'a mut Movie),
+    // Recorded keypad state, the script is ignored
+    Replay(&'a Movie),
+}
+
+struct RunSummary {
+    cycles: u64,
+    frames: u64,
+    error: Option<ChipError>,
+}
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let rom_path = args.get_one::<PathBuf>("rom").expect("ROM is required");
+    let rom =
+        fs::read(rom_path).with_context(|| format!("Failed to open {}", rom_path.display()))?;
+
+    let settings = config::rom_settings(args, &rom)?;
+
+    let replay = args
+        .get_one::<PathBuf>("replay")
+        .map(|path| movie::load(path, &rom))
+        .transpose()?;
+    let (mut config, ipf) = match &replay {
+        Some(movie) => (movie.config, movie.frame_cycles),
+        None => {
+            let config = args::configured_chip_config(args, &settings);
+            let ipf = match config.timing {
+                TimerMode::Cycles(ipf) => ipf,
+                _ => (args::clock_speed(args, &settings) / 60).max(1),
+            };
+            (config, ipf)
+        }
+    };
+    // Timers are driven by the frame loop below
+    config.timing = TimerMode::Manual;
+
+    let mut recording = args
+        .get_one::<PathBuf>("record")
+        .map(|_| Movie::new(&rom, config, ipf, movie::random_seed()));
+    if let Some(movie) = &recording {
+        config = movie.config;
+    }
+
+    let mut emulator = ChipEmulator::with_config(config);
+    emulator.load_rom(rom.as_slice())?;
+    trace::attach(args, &mut emulator)?;
+    // Sound is only written to --wav files
+    let mut audio = Audio::from_args(args, false)?;
+
+    let events = match args.get_one::<PathBuf>("input") {
+        Some(path) => parse_input_script(path)?,
+        None => Vec::new(),
+    };
+
+    let (max_frames, max_cycles) =
+        match (args.get_one::<u64>("frames"), args.get_one::<u64>("cycles")) {
+            // Replays run until the recorded input ends
+            _ if replay.is_some() => (None, None),
+            (Some(frames), _) => (Some(*frames), None),
+            (None, Some(cycles)) => (None, Some(*cycles)),
+            (None, None) => (Some(DEFAULT_FRAMES), None),
+        };
+
+    let movie_mode = match (&replay, &mut recording) {
+        (Some(movie), _) => MovieMode::Replay(movie),
+        (None, Some(movie)) => MovieMode::Record(movie),
+        (None, None) => MovieMode::Off,
+    };
+    let summary = execute(
+        &mut emulator,
+        ipf,
+        &events,
+        max_frames,
+        max_cycles,
+        movie_mode,
+        audio.as_mut(),
+    );
+
+    if let (Some(movie), Some(path)) = (&mut recording, args.get_one::<PathBuf>("record")) {
+        movie.finish(&emulator)?;
+        movie::save(movie, path)?;
+    }
+
+    match args.get_one::<String>("dump_display").map(String::as_str) {
+        Some("ascii") => {
+            let art = display_ascii(&emulator);
+            match args.get_one::<PathBuf>("output") {
+                Some(path) => fs::write(path, art)?,
+                None => io::stdout().write_all(art.as_bytes())?,
+            }
+        }
+        Some("png") => {
+            let path = args
+                .get_one::<PathBuf>("output")
+                .expect("Output path is required for PNG dumps");
+            let palette = args
+                .get_one::<Palette>("palette")
+                .copied()
+                .or(settings.palette)
+                .unwrap_or(PALETTE);
+            write_png(&emulator, &palette, path)?;
+        }
+        _ => (),
+    }
+
+    if let Some(path) = args.get_one::<PathBuf>("registers") {
+        let state = serde_json::to_string_pretty(&registers_json(&emulator, &summary))?;
+        if path.as_os_str() == "-" {
+            println!("{}", state);
+        } else {
+            fs::write(path, state)?;
+        }
+    }
+
+    eprintln!(
+        "Stopped after {} cycles ({} frames)",
+        summary.cycles, summary.frames
+    );
+
+    if let Some(movie) = &replay {
+        let divergences = movie.divergences(&emulator)?;
+        if !divergences.is_empty() {
+            bail!(
+                "Replay diverged from the recording: {} differ",
+                divergences.join(" and ")
+            );
+        }
+        eprintln!("Replay matches the recorded final state");
+    }
+
+    match summary.error {
+        Some(err) => Err(anyhow!(err).context("Emulation halted")),
+        None => Ok(()),
+    }
+}
+
+fn execute(
+    emulator: &mut ChipEmulator,
+    ipf: u32,
+    events: &[KeyEvent],
+    max_frames: Option<u64>,
+    max_cycles: Option<u64>,
+    mut movie: MovieMode,
+    mut audio: Option<&mut Audio>,
+) -> RunSummary {
+    let mut summary = RunSummary {
+        cycles: 0,
+        frames: 0,
+        error: None,
+    };
+    let mut events = events.iter().peekable();
+
+    'run: loop {
+        if max_frames.is_some_and(|max| summary.frames >= max) {
+            break;
+        }
+
+        if let MovieMode::Replay(movie) = &movie {
+            match movie.keypad(summary.frames as usize) {
+                Some(keypad) => emulator.set_keypad(keypad),
+                None => break,
+            }
+        }
+        while let Some(event) = events.next_if(|event| event.frame <= summary.frames) {
+            if event.pressed {
+                emulator.press(event.key);
+            } else {
+                emulator.release(event.key);
+            }
+        }
+        if let MovieMode::Record(movie) = &mut movie {
+            movie.record_frame(emulator.keypad());
+        }
+        if let Some(audio) = &mut audio {
+            audio.frame(emulator);
+        }
+
+        for _ in 0..ipf {
+            if max_cycles.is_some_and(|max| summary.cycles >= max) {
+                break 'run;
+            }
+
+            // Only instructions that ran count, not waits for a key or the next frame
+            match emulator.tick() {
+                Ok(StepOutcome::Exited) => {
+                    summary.cycles += 1;
+                    break 'run;
+                }
+                Ok(StepOutcome::WaitingForVBlank) => break,
+                Ok(StepOutcome::WaitingForKey) => (),
+                Ok(StepOutcome::Executed) => summary.cycles += 1,
+                Err(err) => {
+                    summary.error = Some(err);
+                    break 'run;
+                }
+            }
+        }
+
+        emulator.tick_timers();
+        summary.frames += 1;
+    }
+
+    summary
+}
+
+// Each non-empty line holds '<frame> +<key>' (press) or '<frame> -<key>' (release), '#' starts a comment
+fn parse_input_script(path: &Path) -> Result<Vec<KeyEvent>> {
+    let script = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read input script {}", path.display()))?;
+    let mut events = Vec::new();
+
+    for (line_no, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let parse_event = || -> Result<KeyEvent> {
+            let (frame, key) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("Expected '<frame> +<key>' or '<frame> -<key>'"))?;
+            let key = key.trim();
+            let pressed = match key.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => bail!("Key must be prefixed with '+' or '-'"),
+            };
+            let key = u8::from_str_radix(&key[1..], 16)
+                .ok()
+                .and_then(|key| ChipKey::try_from(key).ok())
+                .ok_or_else(|| anyhow!("Invalid key '{}'", &key[1..]))?;
+
+            Ok(KeyEvent {
+                frame: frame.parse().context("Invalid frame number")?,
+                key,
+                pressed,
+            })
+        };
+
+        events.push(
+            parse_event().with_context(|| {
+                format!("{}:{}: invalid input event", path.display(), line_no + 1)
+            })?,
+        );
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+pub fn display_ascii(emulator: &ChipEmulator) -> String {
+    let (width, _) = emulator.display_size();
+    let pixels = emulator.display().collect::<Vec<_>>();
+
+    pixels
+        .chunks(width)
+        .flat_map(|row| {
+            row.iter()
+                .map(|pixel| ASCII_PIXELS[*pixel as usize])
+                .chain(Some('\n'))
+        })
+        .collect()
+}
+
+fn write_png(emulator: &ChipEmulator, palette: &Palette, path: &Path) -> Result<()> {
+    let (width, height) = emulator.display_size();
+    let data = emulator
+        .display()
+        .flat_map(|pixel| palette[pixel as usize])
+        .collect::<Vec<_>>();
+
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+
+    Ok(())
+}
+
+fn registers_json(emulator: &ChipEmulator, summary: &RunSummary) -> serde_json::Value {
+    json!({
+        "pc": emulator.pc(),
+        "i": emulator.i(),
+        "sp": emulator.sp(),
+        "v": emulator.registers().as_slice(),
+        "stack": emulator.stack(),
+        "delay": emulator.timers().delay,
+        "sound": emulator.timers().sound,
+        "cycles": summary.cycles,
+        "frames": summary.frames,
+        "error": summary.error.map(|err| err.to_string()),
+    })
+}
@@ -0,0 +1,77 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use rust8::rom::rom_hash_hex;
+
+use crate::{database::Database, palette::format_color};
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let rom_path = args.get_one::<PathBuf>("rom").expect("ROM is required");
+    let rom =
+        fs::read(rom_path).with_context(|| format!("Failed to read {}", rom_path.display()))?;
+    let database = Database::from_args(args)?;
+
+    println!("ROM:          {}", rom_path.display());
+    println!("Size:         {} bytes", rom.len());
+    println!("SHA-1:        {}", rom_hash_hex(&rom));
+
+    let entry = match database.lookup(&rom) {
+        Some(entry) => entry,
+        None => {
+            println!("Not found in the ROM database");
+            return Ok(());
+        }
+    };
+    let program = entry.program;
+    let settings = entry.settings();
+
+    println!("Title:        {}", program.title);
+    if !program.authors.is_empty() {
+        println!("Authors:      {}", program.authors.join(", "));
+    }
+    if let Some(release) = &program.release {
+        println!("Release:      {}", release);
+    }
+    if let Some(file) = &entry.rom.file {
+        println!("File:         {}", file);
+    }
+    if let Some(description) = &program.description {
+        println!("Description:  {}", description);
+    }
+
+    match entry.platform() {
+        Some((id, platform)) => println!("Platform:     {} ({})", platform.name(), id),
+        None if entry.rom.platforms.is_empty() => println!("Platform:     unknown"),
+        None => println!(
+            "Platform:     {} (not supported)",
+            entry.rom.platforms.join(", ")
+        ),
+    }
+    if let Some(clock_speed) = settings.clock_speed {
+        println!("Clock speed:  {} Hz", clock_speed);
+    }
+
+    let config = settings.chip_config();
+    println!("Quirks:       modified_shift = {}", config.modified_shift);
+    println!("              load_store = {:?}", config.load_store);
+    println!("              vf_reset = {}", config.vf_reset);
+    println!("              modified_jump = {}", config.modified_jump);
+    println!("              clip_sprites = {}", config.clip_sprites);
+    println!("              display_wait = {}", config.display_wait);
+
+    if let Some(palette) = settings.palette {
+        let colors: Vec<_> = palette.into_iter().map(format_color).collect();
+        println!("Colours:      {}", colors.join(" "));
+    }
+    if !settings.key_hints.is_empty() {
+        let keys: Vec<_> = settings
+            .key_hints
+            .iter()
+            .map(|(action, key)| format!("{} {:X}", action, *key as u8))
+            .collect();
+        println!("Keys:         {}", keys.join(", "));
+    }
+
+    Ok(())
+}
@@ -1,34 +1,93 @@
 mod args;
+mod asm;
+mod audio;
+mod config;
+mod database;
+mod debugger;
+mod disasm;
+mod gdb;
+mod headless;
+mod info;
+mod movie;
+mod palette;
+mod session;
+mod trace;
+mod tui;
+#[cfg(feature = "gui")]
 mod utils;
 
 use anyhow::Result;
-use game_loop::game_loop;
+use clap::ArgMatches;
 
 fn main() -> Result<()> {
     let args = args::parse_args();
 
-    let (event_loop, window, game) = utils::setup(&args)?;
+    match args.subcommand() {
+        Some(("run", run_args)) if run_args.contains_id("gdb") => gdb::run(run_args),
+        Some(("run", run_args)) if *run_args.get_one::<bool>("headless").unwrap_or(&false) => {
+            headless::run(run_args)
+        }
+        Some(("run", run_args)) if *run_args.get_one::<bool>("tui").unwrap_or(&false) => {
+            tui::run(run_args)
+        }
+        Some(("run", run_args)) => run_gui(run_args),
+        Some(("disasm", disasm_args)) => disasm::run(disasm_args),
+        Some(("asm", asm_args)) => asm::run(asm_args),
+        Some(("debug", debug_args)) => debugger::run(debug_args),
+        Some(("info", info_args)) => info::run(info_args),
+        _ if args.contains_id("gdb") => gdb::run(&args),
+        _ if *args.get_one::<bool>("tui").unwrap_or(&false) => tui::run(&args),
+        _ => run_gui(&args),
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(args: &ArgMatches) -> Result<()> {
+    use game_loop::game_loop;
+    use rust8::emulator::StepOutcome;
+
+    let (event_loop, window, game) = utils::setup(args)?;
+    let clock_speed = game.session.clock_speed();
 
     game_loop(
         event_loop,
         window,
         game,
-        *args
-            .get_one("clock_speed")
-            .expect("Clock speed should have default value"),
+        clock_speed,
         0.1,
-        move |g| {
-            if g.game.rom_loaded && !g.game.paused {
-                g.game.emulator.tick();
+        move |g| match g.game.session.tick() {
+            Ok(StepOutcome::Exited) => g.window.set_title("Rust8 - Program exited"),
+            Err(err) => {
+                eprintln!("Emulation halted: {}", err);
+                g.window.set_title(&format!("Rust8 - {}", err));
             }
+            _ => (),
         },
         move |g| {
-            g.game.draw_screen();
+            if let Err(err) = g.game.draw_screen() {
+                eprintln!("Failed to render: {}", err);
+                g.exit();
+            }
         },
         move |g, event| {
+            let halted = g.game.session.halted();
+
             if g.game.handle_event(event) {
                 g.exit();
             }
+
+            // Dropped ROMs can have a different clock speed
+            g.set_updates_per_second(g.game.session.clock_speed());
+
+            // Halt cleared by loading a new ROM
+            if halted && !g.game.session.halted() {
+                g.window.set_title("Rust8");
+            }
         },
     );
 }
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_args: &ArgMatches) -> Result<()> {
+    anyhow::bail!("Rust8 was built without GUI support, use `rust8 run --headless`")
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use rust8::movie::Movie;
+
+pub fn load(path: &Path, rom: &[u8]) -> Result<Movie> {
+    let reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open movie {}", path.display()))?,
+    );
+    let movie = Movie::read_from(reader)
+        .with_context(|| format!("Failed to read movie {}", path.display()))?;
+
+    if !movie.matches_rom(rom) {
+        bail!("Movie {} was recorded with a different ROM", path.display());
+    }
+
+    Ok(movie)
+}
+
+pub fn save(movie: &Movie, path: &Path) -> Result<()> {
+    let writer = BufWriter::new(
+        File::create(path).with_context(|| format!("Failed to create movie {}", path.display()))?,
+    );
+    movie.write_to(writer)?;
+    eprintln!(
+        "Recorded {} frames to {}",
+        movie.frames.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+// Seed for recordings without --seed, std's randomly keyed hasher avoids another dependency
+pub fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process};
+
+    use rust8::emulator::ChipEmulator;
+
+    use super::*;
+    use crate::{args, headless};
+
+    const ROM: &str = "resources/test/random_number_test.ch8";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust8-movie-{}-{}", process::id(), name))
+    }
+
+    fn headless(extra: &[&str]) -> Result<()> {
+        let matches = args::command().get_matches_from(
+            ["rust8", "run", "--headless"]
+                .iter()
+                .chain(extra)
+                .chain(&[ROM]),
+        );
+        let (_, run_args) = matches.subcommand().expect("run subcommand");
+        headless::run(run_args)
+    }
+
+    #[test]
+    fn headless_replay_verifies_the_final_state() {
+        let (input, movie_path) = (temp_path("input.txt"), temp_path("run.c8m"));
+        // New random numbers on every press of key 0
+        fs::write(&input, "20 +0\n25 -0\n60 +0\n65 -0\n").unwrap();
+
+        let record = headless(&[
+            "--frames",
+            "90",
+            "--input",
+            input.to_str().unwrap(),
+            "--record",
+            movie_path.to_str().unwrap(),
+        ]);
+        let replay = || headless(&["--replay", movie_path.to_str().unwrap()]);
+        let recorded = record.and_then(|_| replay());
+
+        // Change one register of the recorded final state
+        let rom = fs::read(ROM).unwrap();
+        let mut movie = load(&movie_path, &rom).unwrap();
+        let mut emulator = ChipEmulator::init();
+        emulator.load_state(movie.final_state.as_slice()).unwrap();
+        emulator.registers_mut()[0] ^= 1;
+        movie.final_state.clear();
+        emulator.save_state(&mut movie.final_state).unwrap();
+        save(&movie, &movie_path).unwrap();
+        let tampered = replay();
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&movie_path).unwrap();
+
+        recorded.unwrap();
+        let err = tampered.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Replay diverged from the recording: registers differ"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Instant;
+
+// RGBA colour for each bit-plane combination
+pub type Palette = [[u8; 4]; 4];
+
+// Colours for each bit-plane combination: none, plane 1, plane 2, both (XO-CHIP)
+pub const PALETTE: Palette = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+];
+
+pub const PALETTES: [(&str, Palette); 4] = [
+    ("default", PALETTE),
+    // Green phosphor monitor
+    (
+        "green",
+        [
+            [0x00, 0x1A, 0x00, 0xFF],
+            [0x33, 0xFF, 0x66, 0xFF],
+            [0x1F, 0x9E, 0x3F, 0xFF],
+            [0x0F, 0x50, 0x20, 0xFF],
+        ],
+    ),
+    // Amber phosphor monitor
+    (
+        "amber",
+        [
+            [0x1A, 0x0F, 0x00, 0xFF],
+            [0xFF, 0xB0, 0x00, 0xFF],
+            [0xA6, 0x6F, 0x00, 0xFF],
+            [0x55, 0x38, 0x00, 0xFF],
+        ],
+    ),
+    // Greenish handheld LCD, dark pixels on a light background
+    (
+        "lcd",
+        [
+            [0x9B, 0xBC, 0x0F, 0xFF],
+            [0x0F, 0x38, 0x0F, 0xFF],
+            [0x30, 0x62, 0x30, 0xFF],
+            [0x65, 0x8F, 0x1F, 0xFF],
+        ],
+    ),
+];
+
+// Fraction of its brightness an unlit pixel keeps after a 60 Hz frame
+pub const PHOSPHOR_DECAY: f32 = 0.5;
+
+// "#RRGGBB" or "RRGGBB"
+pub fn parse_color(text: &str) -> Option<[u8; 4]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+
+    Some([r, g, b, 0xFF])
+}
+
+pub fn format_color(color: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Name of a built-in palette, or 2 to 4 comma separated colours starting with the background.
+// Missing plane colours are blended from the background and the foreground
+pub fn parse_palette(text: &str) -> Result<Palette, String> {
+    if let Some((_, palette)) = PALETTES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(text))
+    {
+        return Ok(*palette);
+    }
+
+    let names: Vec<_> = PALETTES.iter().map(|(name, _)| *name).collect();
+    if !text.contains(',') {
+        return Err(format!(
+            "Unknown palette '{}', expected one of {} or 2 to 4 colours",
+            text,
+            names.join(", ")
+        ));
+    }
+
+    let colors = text
+        .split(',')
+        .map(|color| {
+            parse_color(color.trim()).ok_or_else(|| format!("Invalid colour '{}'", color.trim()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.len() > 4 {
+        return Err(format!(
+            "Palette has {} colours, at most 4 are used",
+            colors.len()
+        ));
+    }
+
+    let (background, foreground) = (colors[0], colors[1]);
+    let mut palette = [
+        background,
+        foreground,
+        blend(background, foreground, 2.0 / 3.0),
+        blend(background, foreground, 1.0 / 3.0),
+    ];
+    palette[..colors.len()].copy_from_slice(&colors);
+
+    Ok(palette)
+}
+
+fn blend(from: [u8; 4], to: [u8; 4], amount: f32) -> [u8; 4] {
+    let mut color = [0xFF; 4];
+    for channel in 0..3 {
+        let (from, to) = (from[channel] as f32, to[channel] as f32);
+        color[channel] = (from + (to - from) * amount).round() as u8;
+    }
+    color
+}
+
+// Pixel colours with unlit pixels fading towards the background
+#[derive(Debug, Default)]
+pub struct Phosphor {
+    glow: Vec<[f32; 3]>,
+    last_update: Option<Instant>,
+}
+
+impl Phosphor {
+    // Colours of `pixels`, `decay` is per 60 Hz frame, independent of how often this is called
+    pub fn update(&mut self, pixels: &[u8], palette: &Palette, decay: f32) -> Vec<[u8; 4]> {
+        let [r, g, b, _] = palette[0].map(f32::from);
+        if self.glow.len() != pixels.len() {
+            self.glow = vec![[r, g, b]; pixels.len()];
+        }
+
+        let elapsed = self
+            .last_update
+            .replace(Instant::now())
+            .map_or(0.0, |last| last.elapsed().as_secs_f32().min(1.0));
+        let fade = decay.powf(elapsed * 60.0);
+
+        self.glow
+            .iter_mut()
+            .zip(pixels)
+            .map(|(glow, pixel)| {
+                let color = palette[*pixel as usize];
+                let mut shown = [0xFF; 4];
+                for channel in 0..3 {
+                    let target = f32::from(color[channel]);
+                    glow[channel] = if *pixel != 0 {
+                        target
+                    } else {
+                        target + (glow[channel] - target) * fade
+                    };
+                    shown[channel] = glow[channel].round() as u8;
+                }
+                shown
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,685 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use game_loop::{GameLoop, Time};
+use rust8::{
+    emulator::{ChipConfig, ChipEmulator, StepOutcome},
+    error::ChipError,
+    keypad::ChipKey,
+    movie::Movie,
+    rewind::RewindBuffer,
+    timers::TimerMode,
+};
+
+use crate::{
+    args,
+    audio::Audio,
+    config::{self, Bindings, Config},
+    database::Database,
+    movie,
+    palette::{Palette, PALETTE, PHOSPHOR_DECAY},
+    trace,
+};
+
+const STATE_SLOTS: u8 = 10;
+// About 10 seconds of history, captured every other frame
+const REWIND_INTERVAL: u32 = 2;
+const REWIND_CAPACITY: usize = 10 * 60 / REWIND_INTERVAL as usize;
+// Frontends without a window event loop redraw at about 60 Hz
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+// Game loop updates run this many instructions while the speed up key is held
+const SPEED_UP: u32 = 4;
+
+// Draws the emulator display, called once per frame of the game loop
+pub trait Renderer {
+    fn render(&mut self, session: &Session) -> Result<()>;
+
+    // Shows a message reported by the session, printed to the console by default
+    fn message(&mut self, message: &Message) {
+        message.print();
+    }
+}
+
+// Translates frontend input into session events
+pub trait InputSource {
+    // Appends the events since the last poll
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputEvent {
+    // Keypad key pressed or released
+    Key(ChipKey, bool),
+    Pause,
+    Reset,
+    // Rewind key pressed or released
+    Rewind(bool),
+    // Speed up key pressed or released
+    SpeedUp(bool),
+    SaveState,
+    LoadState,
+    PreviousSlot,
+    NextSlot,
+    // Dropped onto the window
+    #[cfg(feature = "gui")]
+    LoadRom(PathBuf),
+    Quit,
+}
+
+// Actions bound to keys in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    Quit,
+    SaveState,
+    LoadState,
+    PreviousSlot,
+    NextSlot,
+    Rewind,
+    SpeedUp,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 9] = [
+        Self::Pause,
+        Self::Reset,
+        Self::Quit,
+        Self::SaveState,
+        Self::LoadState,
+        Self::PreviousSlot,
+        Self::NextSlot,
+        Self::Rewind,
+        Self::SpeedUp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::Reset => "reset",
+            Self::Quit => "quit",
+            Self::SaveState => "save_state",
+            Self::LoadState => "load_state",
+            Self::PreviousSlot => "previous_slot",
+            Self::NextSlot => "next_slot",
+            Self::Rewind => "rewind",
+            Self::SpeedUp => "speed_up",
+        }
+    }
+
+    // Rewind and speed up act while the key is held, the rest once per press
+    pub fn held(&self) -> bool {
+        matches!(self, Self::Rewind | Self::SpeedUp)
+    }
+
+    // Event for a key state change, None for releases of press hotkeys
+    pub fn event(&self, pressed: bool) -> Option<InputEvent> {
+        Some(match self {
+            Self::Rewind => InputEvent::Rewind(pressed),
+            Self::SpeedUp => InputEvent::SpeedUp(pressed),
+            _ if !pressed => return None,
+            Self::Pause => InputEvent::Pause,
+            Self::Reset => InputEvent::Reset,
+            Self::Quit => InputEvent::Quit,
+            Self::SaveState => InputEvent::SaveState,
+            Self::LoadState => InputEvent::LoadState,
+            Self::PreviousSlot => InputEvent::PreviousSlot,
+            Self::NextSlot => InputEvent::NextSlot,
+        })
+    }
+}
+
+// Status line for the frontend, errors are shown differently
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub text: String,
+    pub error: bool,
+}
+
+impl Message {
+    pub fn print(&self) {
+        if self.error {
+            eprintln!("{}", self.text);
+        } else {
+            println!("{}", self.text);
+        }
+    }
+}
+
+// Movie being recorded or played back, movies run with TimerMode::Manual
+enum MovieSession {
+    Recording { movie: Movie, path: PathBuf },
+    Replaying { movie: Movie, frame: usize },
+}
+
+// Frontend independent host, owns the emulator and everything around running a ROM
+pub struct Session {
+    pub emulator: ChipEmulator,
+    pub paused: bool,
+    pub exited: bool,
+    pub error: Option<ChipError>,
+
+    rom_path: Option<PathBuf>,
+    rom: Vec<u8>,
+    state_slot: u8,
+
+    rewind: RewindBuffer,
+    // Rewind / speed up key held
+    rewinding: bool,
+    speeding_up: bool,
+    // Instructions per 60 Hz frame and instructions executed in the current one
+    frame_cycles: u32,
+    cycles: u32,
+    movie: Option<MovieSession>,
+    audio: Option<Audio>,
+    messages: Vec<Message>,
+
+    // Command line, config file and ROM database the settings of loaded ROMs come from
+    args: ArgMatches,
+    config: Config,
+    database: Database,
+    // Game loop updates per second, key bindings and display look for the frontend
+    clock_speed: u32,
+    bindings: Bindings,
+    palette: Palette,
+    phosphor_decay: f32,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            emulator: ChipEmulator::init(),
+            paused: false,
+            exited: false,
+            error: None,
+            rom_path: None,
+            rom: Vec::new(),
+            state_slot: 0,
+            rewind: RewindBuffer::new(REWIND_CAPACITY, REWIND_INTERVAL),
+            rewinding: false,
+            speeding_up: false,
+            frame_cycles: 1,
+            cycles: 0,
+            movie: None,
+            audio: None,
+            messages: Vec::new(),
+            args: ArgMatches::default(),
+            config: Config::default(),
+            database: Database::bundled(),
+            clock_speed: 1,
+            bindings: Bindings::default(),
+            palette: PALETTE,
+            phosphor_decay: PHOSPHOR_DECAY,
+        }
+    }
+
+    // Emulator config, movie, tracer and audio from the command line, the config file and the
+    // ROM database, loads the ROM if given
+    pub fn from_args(args: &ArgMatches) -> Result<Self> {
+        let mut session = Self::new();
+        session.args = args.clone();
+        session.config = Config::load(args)?;
+        session.database = Database::from_args(args)?;
+
+        let rom_path = args.get_one::<PathBuf>("rom");
+        let rom = rom_path
+            .map(|path| {
+                fs::read(path).with_context(|| format!("Failed to open {}", path.display()))
+            })
+            .transpose()?;
+
+        let mut config = session.apply_settings(rom.as_deref())?;
+        let frame_cycles = session.frame_cycles;
+
+        if let Some(rom) = &rom {
+            if let Some(movie_path) = args.get_one::<PathBuf>("replay") {
+                let movie = movie::load(movie_path, rom)?;
+                config = movie.config;
+                session.movie = Some(MovieSession::Replaying { movie, frame: 0 });
+            } else if let Some(movie_path) = args.get_one::<PathBuf>("record") {
+                let movie = Movie::new(rom, config, frame_cycles, movie::random_seed());
+                config = movie.config;
+                session.movie = Some(MovieSession::Recording {
+                    movie,
+                    path: movie_path.clone(),
+                });
+            }
+        }
+
+        session.emulator.set_config(config);
+        trace::attach(args, &mut session.emulator)?;
+        session.audio = Audio::from_args(args, true)?;
+
+        if let (Some(path), Some(rom)) = (rom_path, rom) {
+            session.start(path, rom)?;
+        }
+
+        Ok(session)
+    }
+
+    // Applies the settings for `rom` except the emulator config, which is returned
+    fn apply_settings(&mut self, rom: Option<&[u8]>) -> Result<ChipConfig> {
+        let settings = self.config.settings(rom, &self.database)?;
+        self.clock_speed = args::clock_speed(&self.args, &settings);
+        self.bindings = settings.bindings.clone();
+        if let Some(keys) = self.args.get_one::<String>("keys") {
+            self.bindings.keypad = config::parse_keys(keys)?;
+        }
+        self.palette = self
+            .args
+            .get_one::<Palette>("palette")
+            .copied()
+            .or(settings.palette)
+            .unwrap_or(PALETTE);
+        self.phosphor_decay = self
+            .args
+            .get_one::<f32>("phosphor_decay")
+            .copied()
+            .or(settings.phosphor_decay)
+            .unwrap_or(PHOSPHOR_DECAY)
+            .clamp(0.0, 1.0);
+
+        let config = args::configured_chip_config(&self.args, &settings);
+        let frame_cycles = match config.timing {
+            TimerMode::Cycles(ipf) => ipf,
+            _ => self.clock_speed / 60,
+        };
+        self.set_frame_cycles(frame_cycles);
+
+        // Keyboard keys the ROM's controls are bound to
+        let hints: Vec<_> = settings
+            .key_hints
+            .iter()
+            .filter_map(|(action, chip_key)| {
+                let (_, key) = self
+                    .bindings
+                    .keypad
+                    .iter()
+                    .find(|(bound, _)| bound == chip_key)?;
+                Some(format!("{} {}", action, key))
+            })
+            .collect();
+        if !hints.is_empty() {
+            self.info(format!("Keys: {}", hints.join(", ")));
+        }
+
+        Ok(config)
+    }
+
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn phosphor_decay(&self) -> f32 {
+        self.phosphor_decay
+    }
+
+    fn rom_loaded(&self) -> bool {
+        self.rom_path.is_some()
+    }
+
+    pub fn halted(&self) -> bool {
+        self.exited || self.error.is_some()
+    }
+
+    fn set_frame_cycles(&mut self, frame_cycles: u32) {
+        self.frame_cycles = frame_cycles.max(1);
+    }
+
+    // Replaces the running program with the settings for it, stopping any movie
+    #[cfg(feature = "gui")]
+    pub fn load_rom(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let rom = fs::read(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+        self.finish_movie();
+        let config = self.apply_settings(Some(&rom))?;
+        self.emulator.set_config(config);
+        self.start(path, rom)
+    }
+
+    // Runs `rom` with the current emulator config
+    fn start(&mut self, path: &Path, rom: Vec<u8>) -> Result<()> {
+        self.emulator.reset();
+        self.emulator.load_rom(rom.as_slice())?;
+        self.rewind.clear();
+        self.exited = false;
+        self.error = None;
+        self.rom = rom;
+        self.rom_path = Some(path.to_path_buf());
+
+        Ok(())
+    }
+
+    // Restarts the loaded program
+    pub fn reset(&mut self) -> Result<()> {
+        if !self.rom_loaded() {
+            return Ok(());
+        }
+
+        self.emulator.reset();
+        self.emulator.load_rom(self.rom.as_slice())?;
+        self.rewind.clear();
+        self.exited = false;
+        self.error = None;
+
+        Ok(())
+    }
+
+    // Save states are stored next to the ROM, one file per slot
+    fn state_path(&self) -> Option<PathBuf> {
+        self.rom_path
+            .as_ref()
+            .map(|path| path.with_extension(format!("ss{}", self.state_slot)))
+    }
+
+    pub fn save_state(&mut self) -> Result<()> {
+        if let Some(path) = self.state_path() {
+            let writer = BufWriter::new(File::create(&path)?);
+            self.emulator.save_state(writer)?;
+            self.info(format!("Saved state to {}", path.display()));
+        }
+
+        Ok(())
+    }
+
+    pub fn load_state(&mut self) -> Result<()> {
+        if let Some(path) = self.state_path() {
+            let reader = BufReader::new(File::open(&path)?);
+            self.emulator.load_state(reader)?;
+            self.exited = false;
+            self.error = None;
+            self.info(format!("Loaded state from {}", path.display()));
+        }
+
+        Ok(())
+    }
+
+    fn info(&mut self, text: String) {
+        self.messages.push(Message { text, error: false });
+    }
+
+    fn warn(&mut self, text: String) {
+        self.messages.push(Message { text, error: true });
+    }
+
+    // Messages reported since the last call, oldest first
+    pub fn take_messages(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.messages)
+    }
+
+    // Steps back one capture per frame while the rewind key is held
+    fn rewind_frame(&mut self) {
+        match self.rewind.rewind(&mut self.emulator, 1) {
+            Ok(0) => (),
+            Ok(_) => {
+                self.exited = false;
+                self.error = None;
+            }
+            Err(err) => self.warn(format!("Failed to rewind: {}", err)),
+        }
+    }
+
+    // Saves the recording or reports whether the replay matched it
+    fn finish_movie(&mut self) {
+        match self.movie.take() {
+            Some(MovieSession::Recording { mut movie, path }) => {
+                let saved = movie
+                    .finish(&self.emulator)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| movie::save(&movie, &path));
+                if let Err(err) = saved {
+                    self.warn(format!("Failed to save movie: {}", err));
+                }
+            }
+            Some(MovieSession::Replaying { movie, .. }) => {
+                match movie.divergences(&self.emulator) {
+                    Ok(divergences) if divergences.is_empty() => {
+                        self.info("Replay finished, final state matches the recording".to_string())
+                    }
+                    Ok(divergences) => self.info(format!(
+                        "Replay finished, {} diverged from the recording",
+                        divergences.join(" and ")
+                    )),
+                    Err(err) => self.warn(format!("Failed to verify replay: {}", err)),
+                }
+            }
+            None => (),
+        }
+    }
+
+    // Ends the session, saving a movie being recorded
+    pub fn finish(&mut self) {
+        self.finish_movie();
+    }
+
+    // Runs a whole frame at once, taking input from the movie when replaying
+    fn run_frame(&mut self) -> Result<StepOutcome, ChipError> {
+        match &mut self.movie {
+            Some(MovieSession::Recording { movie, .. }) => {
+                movie.record_frame(self.emulator.keypad())
+            }
+            Some(MovieSession::Replaying { movie, frame }) => match movie.keypad(*frame) {
+                Some(keypad) => {
+                    self.emulator.set_keypad(keypad);
+                    *frame += 1;
+                }
+                None => {
+                    self.finish_movie();
+                    return Ok(StepOutcome::Executed);
+                }
+            },
+            None => (),
+        }
+
+        let instructions = match &self.movie {
+            Some(MovieSession::Recording { movie, .. } | MovieSession::Replaying { movie, .. }) => {
+                movie.frame_cycles
+            }
+            None => self.frame_cycles,
+        };
+        let result = self.emulator.run_frame(instructions);
+        let result = self.handle_outcome(result);
+        if self.halted() && matches!(self.movie, Some(MovieSession::Replaying { .. })) {
+            self.finish_movie();
+        }
+
+        result
+    }
+
+    fn handle_outcome(
+        &mut self,
+        result: Result<StepOutcome, ChipError>,
+    ) -> Result<StepOutcome, ChipError> {
+        match result {
+            Ok(StepOutcome::Exited) => {
+                self.exited = true;
+                Ok(StepOutcome::Exited)
+            }
+            Err(err) => {
+                self.error = Some(err);
+                Err(err)
+            }
+            outcome => outcome,
+        }
+    }
+
+    // One game loop update, called at the clock speed
+    pub fn tick(&mut self) -> Result<StepOutcome, ChipError> {
+        if !self.speeding_up {
+            return self.step();
+        }
+
+        let mut outcome = Ok(StepOutcome::Executed);
+        for _ in 0..SPEED_UP {
+            outcome = self.step();
+            if outcome.is_err() {
+                break;
+            }
+        }
+        outcome
+    }
+
+    fn step(&mut self) -> Result<StepOutcome, ChipError> {
+        if !self.rom_loaded() {
+            return Ok(StepOutcome::Executed);
+        }
+
+        self.cycles += 1;
+        let frame_done = self.cycles >= self.frame_cycles;
+        if frame_done {
+            self.cycles = 0;
+        }
+
+        // Rewinding would desync a movie
+        if self.rewinding && self.movie.is_none() {
+            if frame_done {
+                self.rewind_frame();
+            }
+            return Ok(StepOutcome::Executed);
+        }
+        if self.paused || self.halted() {
+            return Ok(StepOutcome::Executed);
+        }
+
+        if frame_done {
+            if let Err(err) = self.rewind.frame(&self.emulator) {
+                self.warn(format!("Failed to capture rewind state: {}", err));
+            }
+            if let Some(audio) = &mut self.audio {
+                audio.frame(&self.emulator);
+            }
+        }
+
+        if self.emulator.config().timing == TimerMode::Manual {
+            return if frame_done {
+                self.run_frame()
+            } else {
+                Ok(StepOutcome::Executed)
+            };
+        }
+
+        let result = self.emulator.tick();
+        self.handle_outcome(result)
+    }
+
+    // Returns true on a quit request
+    pub fn handle(&mut self, event: InputEvent) -> bool {
+        // Replays take input from the movie, resets and loads would desync it
+        let movie = self.movie.is_some();
+        let replaying = matches!(self.movie, Some(MovieSession::Replaying { .. }));
+
+        match event {
+            InputEvent::Key(key, pressed) if !replaying => {
+                if pressed {
+                    self.emulator.press(key);
+                } else {
+                    self.emulator.release(key);
+                }
+            }
+            InputEvent::Key(..) => (),
+            InputEvent::Pause => self.paused = !self.paused,
+            InputEvent::Reset if !movie => {
+                if let Err(err) = self.reset() {
+                    self.warn(format!("Failed to reset: {}", err));
+                }
+            }
+            InputEvent::Reset => (),
+            InputEvent::Rewind(held) => self.rewinding = held,
+            InputEvent::SpeedUp(held) => self.speeding_up = held,
+            InputEvent::SaveState => {
+                if let Err(err) = self.save_state() {
+                    self.warn(format!("Failed to save state: {}", err));
+                }
+            }
+            InputEvent::LoadState if !movie => {
+                if let Err(err) = self.load_state() {
+                    self.warn(format!("Failed to load state: {}", err));
+                }
+            }
+            InputEvent::LoadState => (),
+            InputEvent::PreviousSlot => {
+                self.state_slot = (self.state_slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                self.info(format!("Selected state slot {}", self.state_slot));
+            }
+            InputEvent::NextSlot => {
+                self.state_slot = (self.state_slot + 1) % STATE_SLOTS;
+                self.info(format!("Selected state slot {}", self.state_slot));
+            }
+            #[cfg(feature = "gui")]
+            InputEvent::LoadRom(path) => {
+                if let Err(err) = self.load_rom(path) {
+                    self.warn(format!("Failed to load ROM: {:#}", err));
+                }
+            }
+            InputEvent::Quit => return true,
+        }
+
+        false
+    }
+
+    // Handles all pending input, returns true on a quit request
+    pub fn handle_input(&mut self, input: &mut dyn InputSource) -> Result<bool> {
+        let mut events = Vec::new();
+        input.poll(&mut events)?;
+
+        let mut quit = false;
+        for event in events {
+            quit |= self.handle(event);
+        }
+
+        Ok(quit)
+    }
+}
+
+// Game loop for frontends without a window event loop, `clock_speed` updates per second
+// like the windowed frontend
+pub fn run(
+    session: &mut Session,
+    renderer: &mut dyn Renderer,
+    input: &mut dyn InputSource,
+) -> Result<()> {
+    let clock_speed = session.clock_speed();
+    let mut game_loop = GameLoop::<_, Time, _>::new(session, clock_speed, 0.1, ());
+    let mut result = Ok(());
+
+    while game_loop.next_frame(
+        |g| {
+            g.game.tick().ok();
+        },
+        |g| {
+            let quit = g.game.handle_input(input);
+            for message in g.game.take_messages() {
+                renderer.message(&message);
+            }
+            let frame = quit.and_then(|quit| renderer.render(g.game).map(|_| quit));
+            match frame {
+                Ok(false) => (),
+                Ok(true) => g.exit(),
+                Err(err) => {
+                    result = Err(err);
+                    g.exit();
+                }
+            }
+        },
+    ) {
+        thread::sleep(FRAME_TIME);
+    }
+
+    result
+}
@@ -0,0 +1,193 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ArgMatches;
+use rust8::{
+    disasm::{self, Syntax},
+    emulator::ChipEmulator,
+    trace::{TraceEntry, Tracer},
+};
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TraceFormat {
+    Text,
+    Jsonl,
+    // Fixed 32 byte little-endian records: cycle (u64), PC, opcode, I (u16), DT, ST, V0-VF (u8)
+    Binary,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TraceFilter {
+    // Inclusive PC range
+    range: Option<(u16, u16)>,
+    // Bit n set traces opcodes nXXX
+    classes: Option<u16>,
+}
+
+impl TraceFilter {
+    fn matches(&self, entry: &TraceEntry) -> bool {
+        let in_range = self
+            .range
+            .is_none_or(|(start, end)| (start..=end).contains(&entry.pc));
+        let in_class = self
+            .classes
+            .is_none_or(|classes| classes & (1 << (entry.opcode >> 12)) != 0);
+
+        in_range && in_class
+    }
+}
+
+struct FileTracer {
+    writer: BufWriter<File>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    // Set after the first write error, tracing stops from then on
+    failed: bool,
+}
+
+impl FileTracer {
+    fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", text_line(entry)),
+            TraceFormat::Jsonl => writeln!(self.writer, "{}", json_line(entry)),
+            TraceFormat::Binary => {
+                let mut record = [0; 32];
+                record[..8].copy_from_slice(&entry.cycle.to_le_bytes());
+                record[8..10].copy_from_slice(&entry.pc.to_le_bytes());
+                record[10..12].copy_from_slice(&entry.opcode.to_le_bytes());
+                record[12..14].copy_from_slice(&entry.i.to_le_bytes());
+                record[14] = entry.delay;
+                record[15] = entry.sound;
+                record[16..].copy_from_slice(&entry.after);
+                self.writer.write_all(&record)
+            }
+        }
+    }
+}
+
+impl Tracer for FileTracer {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.failed || !self.filter.matches(entry) {
+            return;
+        }
+
+        if let Err(err) = self.write(entry) {
+            eprintln!("Failed to write trace, tracing stopped: {}", err);
+            self.failed = true;
+        }
+    }
+}
+
+// Attaches a tracer writing to the --trace file, if one was given
+pub fn attach(args: &ArgMatches, emulator: &mut ChipEmulator) -> Result<()> {
+    let path = match args.get_one::<PathBuf>("trace") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let format = match args.get_one::<String>("trace_format").map(String::as_str) {
+        Some("jsonl") => TraceFormat::Jsonl,
+        Some("binary") => TraceFormat::Binary,
+        _ => TraceFormat::Text,
+    };
+    let filter = TraceFilter {
+        range: args
+            .get_one::<String>("trace_range")
+            .map(|range| parse_range(range))
+            .transpose()?,
+        classes: args
+            .get_one::<String>("trace_opcodes")
+            .map(|classes| parse_classes(classes))
+            .transpose()?,
+    };
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create trace file {}", path.display()))?;
+
+    emulator.set_tracer(FileTracer {
+        writer: BufWriter::new(file),
+        format,
+        filter,
+        failed: false,
+    });
+
+    Ok(())
+}
+
+fn text_line(entry: &TraceEntry) -> String {
+    let mut line = format!(
+        "{:>10}  {:04X}  {:04X}  {:<20}  I={:04X} DT={:02X} ST={:02X}",
+        entry.cycle,
+        entry.pc,
+        entry.opcode,
+        disasm::mnemonic(&entry.instruction, Syntax::Cowgod),
+        entry.i,
+        entry.delay,
+        entry.sound
+    );
+    for (x, old, new) in entry.deltas() {
+        let _ = write!(line, "  V{:X}: {:02X}->{:02X}", x, old, new);
+    }
+    line
+}
+
+fn json_line(entry: &TraceEntry) -> Value {
+    let deltas: Map<String, Value> = entry
+        .deltas()
+        .map(|(x, old, new)| (format!("V{:X}", x), json!([old, new])))
+        .collect();
+
+    json!({
+        "cycle": entry.cycle,
+        "pc": entry.pc,
+        "opcode": entry.opcode,
+        "mnemonic": disasm::mnemonic(&entry.instruction, Syntax::Cowgod),
+        "v": entry.after,
+        "deltas": deltas,
+        "i": entry.i,
+        "dt": entry.delay,
+        "st": entry.sound,
+    })
+}
+
+// `START-END` with hex addresses, e.g. 0x200-0x2FF
+fn parse_range(text: &str) -> Result<(u16, u16)> {
+    let parse = |address: &str| {
+        let digits = address
+            .trim()
+            .trim_start_matches("0x")
+            .trim_start_matches('$');
+        u16::from_str_radix(digits, 16).map_err(|_| anyhow!("Invalid address '{}'", address))
+    };
+
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Expected a range like 0x200-0x2FF, got '{}'", text))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        bail!(
+            "Trace range start 0x{:X} is after its end 0x{:X}",
+            start,
+            end
+        );
+    }
+
+    Ok((start, end))
+}
+
+// Comma separated leading opcode nibbles, e.g. `D,F` traces DXYN and FXNN only
+fn parse_classes(text: &str) -> Result<u16> {
+    text.split(',').try_fold(0, |classes, class| {
+        let class = class.trim();
+        let nibble = u8::from_str_radix(class, 16)
+            .ok()
+            .filter(|nibble| *nibble <= 0xF)
+            .ok_or_else(|| anyhow!("Invalid opcode class '{}', expected 0-F", class))?;
+        Ok(classes | 1 << nibble)
+    })
+}
@@ -0,0 +1,347 @@
+use std::{
+    io::{self, Stdout, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use clap::ArgMatches;
+use crossterm::{
+    cursor,
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use rust8::keypad::ChipKey;
+
+use crate::{
+    config::{Bindings, Key},
+    palette::Phosphor,
+    session::{self, Hotkey, InputEvent, InputSource, Message, Renderer, Session},
+};
+
+// Most terminals only repeat presses, keys are released this long after the last one
+const HOLD_TIME: Duration = Duration::from_millis(150);
+
+// Raw mode and alternate screen, restored when dropped
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            cursor::Hide,
+            Clear(ClearType::All)
+        )?;
+        // Only terminals supporting the kitty keyboard protocol report key releases
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )
+        .ok();
+
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        execute!(stdout, PopKeyboardEnhancementFlags).ok();
+        execute!(stdout, ResetColor, cursor::Show, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+// Every cell shows two pixels with the upper half block, only changed cells are written
+struct TerminalRenderer {
+    out: Stdout,
+    // Colour pairs drawn in every terminal cell, None forces a redraw
+    cells: Vec<Option<([u8; 4], [u8; 4])>>,
+    phosphor: Phosphor,
+    display_size: (usize, usize),
+    terminal_size: (u16, u16),
+    status: String,
+    message: Option<Message>,
+    // Bound keys of the main hotkeys
+    help: String,
+}
+
+impl TerminalRenderer {
+    fn new(bindings: &Bindings) -> Self {
+        let help = [Hotkey::Pause, Hotkey::Reset, Hotkey::Quit]
+            .into_iter()
+            .filter_map(|hotkey| {
+                let key = bindings.hotkey(hotkey)?;
+                Some(format!("{}: {}", key, hotkey.name()))
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        Self {
+            out: io::stdout(),
+            cells: Vec::new(),
+            phosphor: Phosphor::default(),
+            display_size: (0, 0),
+            terminal_size: (0, 0),
+            status: String::new(),
+            message: None,
+            help,
+        }
+    }
+
+    fn status(&self, session: &Session) -> String {
+        let state = match (&session.error, session.exited, session.paused) {
+            (Some(err), ..) => format!("Emulation halted: {}", err),
+            (None, true, _) => "Program exited".to_string(),
+            (None, false, true) => "Paused".to_string(),
+            (None, false, false) => String::new(),
+        };
+
+        // The display needs one row for every two pixel rows, plus the status line
+        let (width, height) = self.display_size;
+        let needed = (width, height.div_ceil(2) + 1);
+        let (cols, rows) = (self.terminal_size.0 as usize, self.terminal_size.1 as usize);
+        let too_small = if cols < needed.0 || rows < needed.1 {
+            format!("Terminal too small, {}x{} needed", needed.0, needed.1)
+        } else {
+            String::new()
+        };
+
+        // Longer lines would wrap and scroll the display
+        [too_small.as_str(), state.as_str(), self.help.as_str()]
+            .into_iter()
+            .chain(self.message.as_ref().map(|message| message.text.as_str()))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("  ")
+            .chars()
+            .take(cols)
+            .collect()
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn render(&mut self, session: &Session) -> Result<()> {
+        // Terminal contents are lost on resize, draw everything again
+        let display_size = session.emulator.display_size();
+        let terminal_size = terminal::size()?;
+        if display_size != self.display_size || terminal_size != self.terminal_size {
+            self.display_size = display_size;
+            self.terminal_size = terminal_size;
+            self.cells.clear();
+            self.status.clear();
+            queue!(self.out, Clear(ClearType::All))?;
+        }
+
+        let (width, height) = display_size;
+        let rows = height.div_ceil(2);
+        self.cells.resize(width * rows, None);
+        // Clipped to the terminal, keeping its last row for the status line
+        let visible_width = width.min(terminal_size.0 as usize);
+        let visible_rows = rows.min((terminal_size.1 as usize).saturating_sub(1));
+
+        let pixels: Vec<u8> = session.emulator.display().collect();
+        let colors = self
+            .phosphor
+            .update(&pixels, session.palette(), session.phosphor_decay());
+        // Cell the cursor is left at, consecutive cells don't need a cursor move
+        let mut cursor = None;
+        for row in 0..visible_rows {
+            for x in 0..visible_width {
+                let top = colors[row * 2 * width + x];
+                let bottom = colors
+                    .get((row * 2 + 1) * width + x)
+                    .copied()
+                    .unwrap_or(session.palette()[0]);
+
+                let cell = &mut self.cells[row * width + x];
+                if *cell == Some((top, bottom)) {
+                    continue;
+                }
+                *cell = Some((top, bottom));
+
+                if cursor != Some((x, row)) {
+                    queue!(self.out, cursor::MoveTo(x as u16, row as u16))?;
+                }
+                queue!(
+                    self.out,
+                    SetForegroundColor(color(top)),
+                    SetBackgroundColor(color(bottom)),
+                    Print('▀')
+                )?;
+                cursor = Some((x + 1, row));
+            }
+        }
+
+        let status = self.status(session);
+        if status != self.status {
+            queue!(
+                self.out,
+                ResetColor,
+                cursor::MoveTo(0, visible_rows as u16),
+                Clear(ClearType::CurrentLine),
+                Print(&status)
+            )?;
+            self.status = status;
+        }
+
+        if cursor.is_some() {
+            queue!(self.out, ResetColor)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    // Printing would scroll the screen, messages go to the status line instead
+    fn message(&mut self, message: &Message) {
+        self.message = Some(message.clone());
+    }
+}
+
+struct TerminalInput {
+    keypad: Vec<(ChipKey, KeyCode)>,
+    hotkeys: Vec<(Hotkey, KeyCode)>,
+    // Last press of keys bound to the keypad or to held hotkeys
+    held: Vec<(KeyCode, Instant)>,
+    // Terminal reports key releases, so presses aren't timed out
+    releases: bool,
+}
+
+impl TerminalInput {
+    fn new(bindings: &Bindings) -> Self {
+        Self {
+            keypad: bindings
+                .keypad
+                .iter()
+                .map(|(chip_key, key)| (*chip_key, key_code(*key)))
+                .collect(),
+            hotkeys: bindings
+                .hotkeys
+                .iter()
+                .map(|(hotkey, key)| (*hotkey, key_code(*key)))
+                .collect(),
+            held: Vec::new(),
+            releases: false,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, events: &mut Vec<InputEvent>) {
+        if key.kind == KeyEventKind::Release {
+            self.releases = true;
+        }
+        let pressed = key.kind != KeyEventKind::Release;
+
+        // Raw mode swallows the interrupt signal
+        if pressed
+            && key.code == KeyCode::Char('c')
+            && key.modifiers.contains(KeyModifiers::CONTROL)
+        {
+            events.push(InputEvent::Quit);
+            return;
+        }
+
+        let code = match key.code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        self.held.retain(|(held, _)| *held != code);
+        if pressed && self.holds(code) {
+            self.held.push((code, Instant::now()));
+        }
+        self.events(code, key.kind, events);
+    }
+
+    // Key is bound to something that stays active while held
+    fn holds(&self, code: KeyCode) -> bool {
+        self.keypad.iter().any(|(_, bound)| *bound == code)
+            || self
+                .hotkeys
+                .iter()
+                .any(|(hotkey, bound)| *bound == code && hotkey.held())
+    }
+
+    fn events(&self, code: KeyCode, kind: KeyEventKind, events: &mut Vec<InputEvent>) {
+        let pressed = kind != KeyEventKind::Release;
+
+        for (chip_key, _) in self.keypad.iter().filter(|(_, bound)| *bound == code) {
+            events.push(InputEvent::Key(*chip_key, pressed));
+        }
+        for (hotkey, _) in self.hotkeys.iter().filter(|(_, bound)| *bound == code) {
+            // Key repeats don't toggle pause again
+            if hotkey.held() || kind == KeyEventKind::Press {
+                events.extend(hotkey.event(pressed));
+            }
+        }
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                self.handle_key(key, events);
+            }
+        }
+
+        if self.releases {
+            return Ok(());
+        }
+
+        // Release keys whose presses stopped repeating
+        let (expired, held) = self
+            .held
+            .drain(..)
+            .partition(|(_, time)| time.elapsed() >= HOLD_TIME);
+        self.held = held;
+        for (code, _) in expired {
+            self.events(code, KeyEventKind::Release, events);
+        }
+
+        Ok(())
+    }
+}
+
+fn key_code(key: Key) -> KeyCode {
+    match key {
+        Key::Char(c) => KeyCode::Char(c),
+        Key::Space => KeyCode::Char(' '),
+        Key::Escape => KeyCode::Esc,
+        Key::Backspace => KeyCode::Backspace,
+        Key::Tab => KeyCode::Tab,
+        Key::Enter => KeyCode::Enter,
+        Key::Up => KeyCode::Up,
+        Key::Down => KeyCode::Down,
+        Key::Left => KeyCode::Left,
+        Key::Right => KeyCode::Right,
+        Key::F(n) => KeyCode::F(n),
+    }
+}
+
+fn color([r, g, b, _]: [u8; 4]) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+pub fn run(args: &ArgMatches) -> Result<()> {
+    let mut session = Session::from_args(args)?;
+    let mut renderer = TerminalRenderer::new(session.bindings());
+    let mut input = TerminalInput::new(session.bindings());
+
+    let terminal = RawTerminal::enter().context("Failed to set up the terminal")?;
+    let result = session::run(&mut session, &mut renderer, &mut input);
+    drop(terminal);
+
+    // Printed once the terminal is restored
+    session.finish();
+    for message in session.take_messages() {
+        message.print();
+    }
+
+    result
+}
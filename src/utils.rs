@@ -1,17 +1,8 @@
-use std::{
-    collections::HashMap,
-    fs::File,
-    io::BufReader,
-    path::{Path, PathBuf},
-};
-
 use anyhow::Result;
 use clap::ArgMatches;
-use lazy_static::lazy_static;
-use pixels::{Pixels, SurfaceTexture};
+use pixels::{wgpu, Pixels, SurfaceTexture};
 use rust8::{
     display::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
-    emulator::{ChipConfig, ChipEmulator},
     keypad::ChipKey,
 };
 use winit::{
@@ -22,127 +13,264 @@ use winit::{
 };
 use winit_input_helper::WinitInputHelper;
 
-lazy_static! {
-    static ref KEY_MAP: HashMap<ChipKey, VirtualKeyCode> = {
-        let mut m = HashMap::with_capacity(16);
-        m.insert(ChipKey::Key1, VirtualKeyCode::Key1);
-        m.insert(ChipKey::Key2, VirtualKeyCode::Key2);
-        m.insert(ChipKey::Key3, VirtualKeyCode::Key3);
-        m.insert(ChipKey::KeyC, VirtualKeyCode::Key4);
-        m.insert(ChipKey::Key4, VirtualKeyCode::Q);
-        m.insert(ChipKey::Key5, VirtualKeyCode::W);
-        m.insert(ChipKey::Key6, VirtualKeyCode::E);
-        m.insert(ChipKey::KeyD, VirtualKeyCode::R);
-        m.insert(ChipKey::Key7, VirtualKeyCode::A);
-        m.insert(ChipKey::Key8, VirtualKeyCode::S);
-        m.insert(ChipKey::Key9, VirtualKeyCode::D);
-        m.insert(ChipKey::KeyE, VirtualKeyCode::F);
-        m.insert(ChipKey::KeyA, VirtualKeyCode::Z);
-        m.insert(ChipKey::Key0, VirtualKeyCode::X);
-        m.insert(ChipKey::KeyB, VirtualKeyCode::C);
-        m.insert(ChipKey::KeyF, VirtualKeyCode::V);
-        m
-    };
-}
+use crate::{
+    config::{Bindings, Key},
+    palette::Phosphor,
+    session::{Hotkey, InputEvent, InputSource, Renderer, Session},
+};
 
-pub struct Game {
-    pub pixels: Pixels,
-    pub input: WinitInputHelper,
-    pub emulator: ChipEmulator,
-    pub rom_loaded: bool,
-    pub paused: bool,
+// Draws the display into the window through pixels
+pub struct PixelsRenderer {
+    pixels: Pixels,
+    buffer_size: (usize, usize),
+    phosphor: Phosphor,
 }
 
-impl Game {
+impl PixelsRenderer {
     pub fn new(pixels: Pixels) -> Self {
         Self {
             pixels,
-            input: WinitInputHelper::new(),
-            emulator: ChipEmulator::init(),
-            rom_loaded: false,
-            paused: false,
+            buffer_size: (DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            phosphor: Phosphor::default(),
         }
     }
 
-    pub fn set_emulator_config(&mut self, config: ChipConfig) {
-        self.emulator.set_config(config);
+    pub fn resize_surface(&mut self, width: u32, height: u32) {
+        self.pixels.resize_surface(width, height);
     }
+}
 
-    pub fn load_rom(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        let reader = BufReader::new(File::open(path)?);
-        self.emulator.load_rom(reader)?;
-        self.rom_loaded = true;
+impl Renderer for PixelsRenderer {
+    fn render(&mut self, session: &Session) -> Result<()> {
+        let emulator = &session.emulator;
+        let palette = session.palette();
 
-        Ok(())
-    }
+        // Display resolution changed (SUPER-CHIP hires toggle)
+        let size = emulator.display_size();
+        if size != self.buffer_size {
+            self.pixels.resize_buffer(size.0 as u32, size.1 as u32);
+            self.buffer_size = size;
+        }
+
+        // Letterbox around the display in the background colour
+        let [r, g, b, _] = palette[0].map(|channel| channel as f64 / 255.0);
+        self.pixels.set_clear_color(wgpu::Color { r, g, b, a: 1.0 });
 
-    pub fn draw_screen(&mut self) {
+        let pixels: Vec<u8> = emulator.display().collect();
+        let colors = self
+            .phosphor
+            .update(&pixels, palette, session.phosphor_decay());
         self.pixels
             .get_frame()
             .chunks_exact_mut(4)
-            .zip(self.emulator.display())
-            .for_each(|(screen_pxl, emulator_pxl)| {
-                // screen_pxl.fill(if *emulator_pxl { 0xFF } else { 0x00 });
-                if *emulator_pxl {
-                    screen_pxl.fill(0xFF);
-                } else {
-                    screen_pxl[3] /= 2;
-                }
-            });
-
-        self.pixels.render().expect("Failed to render pixels");
+            .zip(colors)
+            .for_each(|(screen_pxl, color)| screen_pxl.copy_from_slice(&color));
+
+        self.pixels.render()?;
+        Ok(())
     }
+}
 
-    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
-        if self.input.update(event) {
-            // Quit requrest
-            if self.input.key_pressed(VirtualKeyCode::Escape) || self.input.quit() {
-                return true;
-            }
+// Keyboard and window events collected by winit_input_helper
+pub struct WinitInput {
+    pub helper: WinitInputHelper,
+    bindings: Bindings,
+    keypad: Vec<(ChipKey, VirtualKeyCode)>,
+    hotkeys: Vec<(Hotkey, VirtualKeyCode)>,
+}
 
-            // Pause request
-            if self.input.key_pressed(VirtualKeyCode::Space) {
-                self.paused = !self.paused;
-            }
+impl WinitInput {
+    pub fn new(bindings: &Bindings) -> Self {
+        let mut input = Self {
+            helper: WinitInputHelper::new(),
+            bindings: bindings.clone(),
+            keypad: Vec::new(),
+            hotkeys: Vec::new(),
+        };
+        input.map_keys();
+        input
+    }
 
-            // Resize request
-            if let Some(size) = self.input.window_resized() {
-                self.pixels.resize_surface(size.width, size.height);
-            }
+    // Dropped ROMs can come with their own bindings
+    pub fn set_bindings(&mut self, bindings: &Bindings) {
+        if *bindings != self.bindings {
+            self.bindings = bindings.clone();
+            self.map_keys();
+        }
+    }
 
-            // File drop
-            if let Some(path) = self.input.dropped_file() {
-                self.emulator.reset();
-                self.load_rom(path).expect("Failed to load ROM");
-            }
+    fn map_keys(&mut self) {
+        self.keypad = self
+            .bindings
+            .keypad
+            .iter()
+            .map(|(chip_key, key)| (*chip_key, virtual_key(*key)))
+            .collect();
+        self.hotkeys = self
+            .bindings
+            .hotkeys
+            .iter()
+            .map(|(hotkey, key)| (*hotkey, virtual_key(*key)))
+            .collect();
+    }
+}
+
+impl InputSource for WinitInput {
+    fn poll(&mut self, events: &mut Vec<InputEvent>) -> Result<()> {
+        let input = &self.helper;
+
+        // Window closed
+        if input.quit() {
+            events.push(InputEvent::Quit);
+        }
 
-            // Normal controls
-            if let Some(chip_key) = self.emulator.current_key() {
-                if self.input.key_released(*KEY_MAP.get(chip_key).unwrap()) {
-                    // Button released - recheck for key press
-                    self.emulator.set_key(None);
-                    self.check_keys();
-                }
+        // Rewind and speed up while held, the rest on press
+        for (hotkey, key) in &self.hotkeys {
+            let active = if hotkey.held() {
+                input.key_held(*key)
             } else {
-                // No button held - check for key press
-                self.check_keys();
-            }
+                input.key_pressed(*key)
+            };
+            events.extend(hotkey.event(active));
+        }
+
+        // File drop
+        if let Some(path) = input.dropped_file() {
+            events.push(InputEvent::LoadRom(path));
+        }
+
+        // Normal controls
+        for (chip_key, key) in &self.keypad {
+            events.push(InputEvent::Key(*chip_key, input.key_held(*key)));
         }
 
-        false
+        Ok(())
     }
+}
+
+fn virtual_key(key: Key) -> VirtualKeyCode {
+    use VirtualKeyCode as Code;
 
-    fn check_keys(&mut self) {
-        for (chip_key, key) in KEY_MAP.iter() {
-            if self.input.key_pressed(*key) {
-                self.emulator.set_key(Some(*chip_key));
-                return;
+    match key {
+        Key::Char(c) => match c {
+            'a' => Code::A,
+            'b' => Code::B,
+            'c' => Code::C,
+            'd' => Code::D,
+            'e' => Code::E,
+            'f' => Code::F,
+            'g' => Code::G,
+            'h' => Code::H,
+            'i' => Code::I,
+            'j' => Code::J,
+            'k' => Code::K,
+            'l' => Code::L,
+            'm' => Code::M,
+            'n' => Code::N,
+            'o' => Code::O,
+            'p' => Code::P,
+            'q' => Code::Q,
+            'r' => Code::R,
+            's' => Code::S,
+            't' => Code::T,
+            'u' => Code::U,
+            'v' => Code::V,
+            'w' => Code::W,
+            'x' => Code::X,
+            'y' => Code::Y,
+            'z' => Code::Z,
+            '0' => Code::Key0,
+            '1' => Code::Key1,
+            '2' => Code::Key2,
+            '3' => Code::Key3,
+            '4' => Code::Key4,
+            '5' => Code::Key5,
+            '6' => Code::Key6,
+            '7' => Code::Key7,
+            '8' => Code::Key8,
+            '9' => Code::Key9,
+            '-' => Code::Minus,
+            '=' => Code::Equals,
+            '[' => Code::LBracket,
+            ']' => Code::RBracket,
+            '\\' => Code::Backslash,
+            ';' => Code::Semicolon,
+            '\'' => Code::Apostrophe,
+            ',' => Code::Comma,
+            '.' => Code::Period,
+            '/' => Code::Slash,
+            _ => Code::Grave,
+        },
+        Key::Space => Code::Space,
+        Key::Escape => Code::Escape,
+        Key::Backspace => Code::Back,
+        Key::Tab => Code::Tab,
+        Key::Enter => Code::Return,
+        Key::Up => Code::Up,
+        Key::Down => Code::Down,
+        Key::Left => Code::Left,
+        Key::Right => Code::Right,
+        Key::F(n) => [
+            Code::F1,
+            Code::F2,
+            Code::F3,
+            Code::F4,
+            Code::F5,
+            Code::F6,
+            Code::F7,
+            Code::F8,
+            Code::F9,
+            Code::F10,
+            Code::F11,
+            Code::F12,
+        ][n as usize - 1],
+    }
+}
+
+pub struct Game {
+    pub session: Session,
+    pub renderer: PixelsRenderer,
+    pub input: WinitInput,
+}
+
+impl Game {
+    pub fn draw_screen(&mut self) -> Result<()> {
+        self.renderer.render(&self.session)
+    }
+
+    // Returns true on a quit request
+    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        if !self.input.helper.update(event) {
+            return false;
+        }
+
+        // Resize request
+        if let Some(size) = self.input.helper.window_resized() {
+            self.renderer.resize_surface(size.width, size.height);
+        }
+
+        let quit = match self.session.handle_input(&mut self.input) {
+            Ok(quit) => quit,
+            Err(err) => {
+                eprintln!("Failed to read input: {}", err);
+                false
             }
+        };
+        if quit {
+            self.session.finish();
         }
+        self.input.set_bindings(self.session.bindings());
+
+        for message in self.session.take_messages() {
+            self.renderer.message(&message);
+        }
+
+        quit
     }
 }
 
 pub fn setup(args: &ArgMatches) -> Result<(EventLoop<()>, Window, Game)> {
+    let session = Session::from_args(args)?;
     let event_loop = EventLoop::new();
 
     let window = {
@@ -163,23 +291,10 @@ pub fn setup(args: &ArgMatches) -> Result<(EventLoop<()>, Window, Game)> {
         Pixels::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32, surface_texture)?
     };
 
-    let game = {
-        let mut game = Game::new(pixels);
-
-        game.set_emulator_config(ChipConfig {
-            modified_shift: *args
-                .get_one("modified_shift")
-                .expect("Flag should have default value"),
-            modified_load: *args
-                .get_one("modified_load")
-                .expect("Flag should have default value"),
-        });
-
-        if let Some(path) = args.get_one::<PathBuf>("rom") {
-            game.load_rom(path)?;
-        }
-
-        game
+    let game = Game {
+        input: WinitInput::new(session.bindings()),
+        session,
+        renderer: PixelsRenderer::new(pixels),
     };
 
     Ok((event_loop, window, game))